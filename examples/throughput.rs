@@ -0,0 +1,39 @@
+//! Measures `insert` and `get` throughput.
+//!
+//! Internal consistency checks run only in debug builds, compare
+//! `cargo run --release --example throughput` and `cargo run --example throughput`
+//! to see what they cost.
+
+use std::{hint::black_box, num::NonZeroUsize, time::Instant};
+
+use lru_cache::LRUCache;
+
+const MAX_LEN: usize = 10_000;
+const OPS: u64 = 5_000_000;
+
+fn main() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(MAX_LEN).unwrap());
+
+    let started = Instant::now();
+    for i in 0..OPS {
+        // a quarter of insertions replace present keys, the rest evict
+        let key = if i % 4 == 0 { i / 2 } else { i };
+        black_box(cache.insert(key, i));
+    }
+    report("insert", started);
+
+    let started = Instant::now();
+    for i in 0..OPS {
+        let key = OPS - 1 - i % (2 * MAX_LEN as u64);
+        black_box(cache.get(&key));
+    }
+    report("get", started);
+}
+
+fn report(name: &str, started: Instant) {
+    let elapsed = started.elapsed();
+    println!(
+        "{name}: {:.1} ns/op",
+        elapsed.as_nanos() as f64 / OPS as f64
+    );
+}
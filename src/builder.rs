@@ -0,0 +1,141 @@
+use std::{collections::hash_map::RandomState, fmt::Debug, hash::BuildHasher, num::NonZeroUsize};
+
+#[cfg(feature = "ttl")]
+use std::time::Duration;
+
+#[cfg(feature = "ttl")]
+use crate::ttl::{SystemClock, Ttl};
+use crate::{EvictionHistory, EvictionListener, EvictionReason, LRUCache, Lru, Policy, TieBreak};
+
+/// Configuration of an [LRUCache], see [LRUCache::builder].
+pub struct LRUCacheBuilder<K, V, S = RandomState, P = Lru> {
+    max_len: Option<NonZeroUsize>,
+    hasher: S,
+    policy: P,
+    tie_break: Option<TieBreak>,
+    #[cfg(feature = "ttl")]
+    time_to_live: Option<Duration>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    eviction_history: Option<EvictionHistory<K>>,
+}
+
+impl<K, V> LRUCacheBuilder<K, V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            max_len: None,
+            hasher: RandomState::new(),
+            policy: Lru,
+            tie_break: None,
+            #[cfg(feature = "ttl")]
+            time_to_live: None,
+            eviction_listener: None,
+            eviction_history: None,
+        }
+    }
+}
+
+impl<K, V, S, P> LRUCacheBuilder<K, V, S, P> {
+    /// Maximum number of elements, `1` unless set.
+    pub fn max_len(mut self, max_len: NonZeroUsize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Hashes keys with `hasher`, see [LRUCache::with_hasher].
+    pub fn hasher<H>(self, hasher: H) -> LRUCacheBuilder<K, V, H, P> {
+        LRUCacheBuilder {
+            max_len: self.max_len,
+            hasher,
+            policy: self.policy,
+            tie_break: self.tie_break,
+            #[cfg(feature = "ttl")]
+            time_to_live: self.time_to_live,
+            eviction_listener: self.eviction_listener,
+            eviction_history: self.eviction_history,
+        }
+    }
+
+    /// Evicts and reorders entries following `policy`, see [LRUCache::with_policy].
+    pub fn policy<P2>(self, policy: P2) -> LRUCacheBuilder<K, V, S, P2> {
+        LRUCacheBuilder {
+            max_len: self.max_len,
+            hasher: self.hasher,
+            policy,
+            tie_break: self.tie_break,
+            #[cfg(feature = "ttl")]
+            time_to_live: self.time_to_live,
+            eviction_listener: self.eviction_listener,
+            eviction_history: self.eviction_history,
+        }
+    }
+
+    /// Orders entries the policy ranks equally, see [Policy::set_tie_break].
+    ///
+    /// [TieBreak::Recency] unless set. Recency alone totally orders entries of the default
+    /// [Lru] policy, so this only matters for policies ranking entries otherwise, e.g. [crate::Lfu].
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = Some(tie_break);
+        self
+    }
+
+    /// Expires entries `time_to_live` after insertion, see [LRUCache::with_ttl].
+    #[cfg(feature = "ttl")]
+    pub fn time_to_live(mut self, time_to_live: Duration) -> Self {
+        self.time_to_live = Some(time_to_live);
+        self
+    }
+
+    /// Registers an eviction listener, see [LRUCache::on_evict].
+    pub fn on_evict<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(K, V, EvictionReason) + 'static,
+    {
+        self.eviction_listener = Some(EvictionListener {
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// Remembers keys of the last `max_len` evicted entries, see [LRUCache::with_eviction_history].
+    pub fn eviction_history(mut self, max_len: usize) -> Self
+    where
+        K: Clone,
+    {
+        self.eviction_history = Some(EvictionHistory::new(max_len));
+        self
+    }
+
+    /// Creates the cache, allocating capacity for `max_len` elements beforehand.
+    pub fn build(mut self) -> LRUCache<K, V, S, P>
+    where
+        S: BuildHasher,
+        P: Policy<K, V>,
+    {
+        let max_len = self.max_len.unwrap_or(NonZeroUsize::MIN);
+        if let Some(tie_break) = self.tie_break {
+            self.policy.set_tie_break(tie_break);
+        }
+        let mut cache = LRUCache::with_hasher(max_len, self.hasher).with_policy(self.policy);
+        #[cfg(feature = "ttl")]
+        if let Some(time_to_live) = self.time_to_live {
+            cache.ttl = Some(Ttl {
+                time_to_live,
+                clock: Box::new(SystemClock),
+            });
+        }
+        cache.eviction_listener = self.eviction_listener;
+        cache.eviction_history = self.eviction_history;
+        cache
+    }
+}
+
+impl<K, V, S, P> Debug for LRUCacheBuilder<K, V, S, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut builder = f.debug_struct("LRUCacheBuilder");
+        builder.field("max_len", &self.max_len);
+        builder.field("tie_break", &self.tie_break);
+        #[cfg(feature = "ttl")]
+        builder.field("time_to_live", &self.time_to_live);
+        builder.finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Debug,
+    hash::{BuildHasher, Hash},
+    mem,
+};
+
+use crate::{LRUCache, Lru, Node, Policy};
+
+/// View into a single entry of the cache, see [LRUCache::entry].
+pub enum Entry<'a, K, V, S = RandomState, P = Lru> {
+    Occupied(OccupiedEntry<'a, K, V, S, P>),
+    Vacant(VacantEntry<'a, K, V, S, P>),
+}
+
+/// Entry of a stored key, which `entry` already made the most recently used one
+/// if the policy promotes retrieved keys.
+///
+/// Accessors, `get` included, neither promote the key again nor count as retrievals.
+pub struct OccupiedEntry<'a, K, V, S = RandomState, P = Lru> {
+    cache: &'a mut LRUCache<K, V, S, P>,
+    /// Stored node of the key, alive while the cache is borrowed
+    node: *const Node<K, V>,
+}
+
+/// Entry of an absent key, inserting into it may evict like `insert`.
+pub struct VacantEntry<'a, K, V, S = RandomState, P = Lru> {
+    cache: &'a mut LRUCache<K, V, S, P>,
+    key: K,
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, P: Policy<K, V>> Entry<'a, K, V, S, P> {
+    /// Returns the key of the entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(occupied) => occupied.key(),
+            Entry::Vacant(vacant) => vacant.key(),
+        }
+    }
+
+    /// Returns the stored value, inserting `default` if the key is absent.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the stored value, inserting `default()` if the key is absent.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(occupied) => occupied.into_mut(),
+            Entry::Vacant(vacant) => vacant.insert(default()),
+        }
+    }
+
+    /// Applies `modify` to the stored value, if there is one.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, modify: F) -> Self {
+        match self {
+            Entry::Occupied(mut occupied) => {
+                modify(occupied.get_mut());
+                Entry::Occupied(occupied)
+            }
+            vacant => vacant,
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, P: Policy<K, V>> OccupiedEntry<'a, K, V, S, P> {
+    pub(crate) fn new(cache: &'a mut LRUCache<K, V, S, P>, node: *const Node<K, V>) -> Self {
+        Self { cache, node }
+    }
+
+    fn node(&self) -> &Node<K, V> {
+        // the node is stored while the cache is borrowed, only `remove_entry` takes it out
+        unsafe { &*self.node }
+    }
+
+    /// Returns the stored key.
+    pub fn key(&self) -> &K {
+        &self.node().key
+    }
+
+    /// Returns the stored value.
+    pub fn get(&self) -> &V {
+        self.node().value()
+    }
+
+    /// Returns the stored value mutably.
+    pub fn get_mut(&mut self) -> &mut V {
+        // the cache is borrowed uniquely, so is the entry
+        unsafe { &mut *self.node().value.get() }
+    }
+
+    /// Converts the entry into a reference to the stored value.
+    pub fn into_mut(self) -> &'a mut V {
+        // the cache stays borrowed uniquely for 'a
+        unsafe { &mut *(*self.node).value.get() }
+    }
+
+    /// Replaces the stored value in place, returning the old one.
+    ///
+    /// Unlike `insert` of the cache, the entry keeps its insertion sequence number
+    /// and expiration time.
+    pub fn insert(&mut self, val: V) -> V {
+        mem::replace(self.get_mut(), val)
+    }
+
+    /// Removes the entry from the cache, returning the stored pair.
+    pub fn remove_entry(self) -> (K, V) {
+        let key = unsafe { &(*self.node).key };
+        let removed = self.cache.take_entry(key);
+        invariant!(removed.is_some());
+        removed.unwrap().into_pair()
+    }
+
+    /// Removes the entry from the cache, returning the stored value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, P: Policy<K, V>> VacantEntry<'a, K, V, S, P> {
+    pub(crate) fn new(cache: &'a mut LRUCache<K, V, S, P>, key: K) -> Self {
+        Self { cache, key }
+    }
+
+    /// Returns the key given to `entry`.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes back the key given to `entry`.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `val`, making the key the last used one, and returns a reference to it.
+    pub fn insert(self, val: V) -> &'a mut V {
+        let cache = self.cache;
+        cache.drop_before_insertion(&self.key);
+        cache.push_entry(self.key, val);
+        cache.check_pressure();
+        unsafe { cache.back_entry().value_mut() }
+    }
+}
+
+impl<K: Debug, V: Debug, S, P> Debug for Entry<'_, K, V, S, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Entry::Occupied(occupied) => f.debug_tuple("Occupied").field(occupied).finish(),
+            Entry::Vacant(vacant) => f.debug_tuple("Vacant").field(vacant).finish(),
+        }
+    }
+}
+
+impl<K: Debug, V: Debug, S, P> Debug for OccupiedEntry<'_, K, V, S, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let node = unsafe { &*self.node };
+        f.debug_struct("OccupiedEntry")
+            .field("key", &node.key)
+            .field("value", node.value())
+            .finish()
+    }
+}
+
+impl<K: Debug, V, S, P> Debug for VacantEntry<'_, K, V, S, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VacantEntry")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
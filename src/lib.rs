@@ -1,17 +1,86 @@
 use std::{
-    borrow::Borrow, cell::RefCell, collections::HashSet, fmt::Debug, hash::Hash, num::NonZeroU32,
-    ops::Deref, ptr, rc::Rc,
+    borrow::Borrow,
+    cell::{Cell, RefCell, UnsafeCell},
+    cmp::Ordering,
+    collections::{hash_map::RandomState, HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    hash::{BuildHasher, Hash},
+    num::NonZeroUsize,
+    ops::{Deref, Index},
+    ptr,
+    rc::Rc,
 };
 
-use intrusive_collections::{intrusive_adapter, LinkedList, LinkedListLink};
+#[cfg(feature = "spill")]
+use std::sync::mpsc::{SendError, Sender};
+#[cfg(feature = "ttl")]
+use std::time::{Duration, Instant};
+
+use intrusive_collections::{intrusive_adapter, linked_list, LinkedList, LinkedListLink};
+
+/// Checks an internal invariant in debug builds, unless the `unchecked` feature is enabled
+macro_rules! invariant {
+    ($($arg:tt)+) => {
+        if cfg!(not(feature = "unchecked")) {
+            debug_assert!($($arg)+);
+        }
+    };
+}
+
+/// Checks an internal invariant in debug builds, unless the `unchecked` feature is enabled
+macro_rules! invariant_eq {
+    ($($arg:tt)+) => {
+        if cfg!(not(feature = "unchecked")) {
+            debug_assert_eq!($($arg)+);
+        }
+    };
+}
+
+mod builder;
+mod entry;
+mod policy;
+mod scoped;
+mod sync;
+#[cfg(feature = "ttl")]
+mod ttl;
+mod two_queue;
+mod weighted;
+
+pub use builder::LRUCacheBuilder;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use policy::{Fifo, Lfu, Lru, Mru, Nodes, Policy, PolicyNode, TieBreak, Victim};
+pub use scoped::EvictOnDrop;
+pub use sync::SyncLRUCache;
+#[cfg(feature = "ttl")]
+use ttl::Ttl;
+#[cfg(feature = "ttl")]
+pub use ttl::{Clock, SystemClock};
+pub use two_queue::TwoQueueCache;
+pub use weighted::WeightedLRUCache;
 
 #[derive(Debug)]
 struct Node<K, V> {
     key: K,
-    value: V,
+    /// Mutated only through a unique borrow of the cache, see [RefNode::value_mut]
+    value: UnsafeCell<V>,
+    insertion_seq: u64,
+    /// Retrievals of the key, including the ones of replaced entries, see [Lfu]
+    accesses: Cell<u64>,
+    /// Segment of a [TwoQueueCache] the node is in
+    protected: Cell<bool>,
+    /// Stamped on insertion into a cache created `with_ttl`
+    #[cfg(feature = "ttl")]
+    written_at: Cell<Option<Instant>>,
     link: LinkedListLink,
 }
 
+impl<K, V> Node<K, V> {
+    fn value(&self) -> &V {
+        unsafe { &*self.value.get() }
+    }
+}
+
 intrusive_adapter!(NodeAdapter<K, V> = Rc<Node<K, V>>: Node<K, V> { link: LinkedListLink });
 
 #[derive(Debug)]
@@ -20,11 +89,16 @@ struct RefNode<K, V> {
 }
 
 impl<K, V> RefNode<K, V> {
-    fn new(key: K, value: V) -> Self {
+    fn new(key: K, value: V, insertion_seq: u64) -> Self {
         Self {
             ref_count: Rc::new(Node {
                 key,
-                value,
+                value: UnsafeCell::new(value),
+                insertion_seq,
+                accesses: Cell::new(0),
+                protected: Cell::new(false),
+                #[cfg(feature = "ttl")]
+                written_at: Cell::new(None),
                 link: LinkedListLink::new(),
             }),
         }
@@ -35,17 +109,29 @@ impl<K, V> RefNode<K, V> {
     }
 
     fn value(&self) -> &V {
-        &self.ref_count.value
+        self.ref_count.value()
     }
 
-    fn into_pair(self) -> (K, V)
-    where
-        K: Debug,
-        V: Debug,
-    {
-        assert_eq!(Rc::strong_count(&self.ref_count), 1);
-        let Node { key, value, .. } = Rc::try_unwrap(self.ref_count).unwrap();
-        (key, value)
+    /// # Safety
+    ///
+    /// No other reference to the value may be alive. Nodes are reachable only through
+    /// the cache, so it holds while the cache is borrowed uniquely for the returned lifetime.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn value_mut(&self) -> &mut V {
+        &mut *self.ref_count.value.get()
+    }
+
+    fn into_pair(self) -> (K, V) {
+        invariant_eq!(Rc::strong_count(&self.ref_count), 1);
+        let Ok(Node { key, value, .. }) = Rc::try_unwrap(self.ref_count) else {
+            panic!("node is still referenced");
+        };
+        (key, value.into_inner())
+    }
+
+    fn count_access(&self) {
+        let accesses = &self.ref_count.accesses;
+        accesses.set(accesses.get() + 1);
     }
 
     fn strong_ref_count(&self) -> usize {
@@ -67,9 +153,28 @@ impl<K: PartialEq, V> PartialEq for RefNode<K, V> {
 
 impl<K: Eq, V> Eq for RefNode<K, V> {}
 
-impl<K, V> Borrow<K> for RefNode<K, V> {
-    fn borrow(&self) -> &K {
-        self.key()
+/// Borrowed form of a key, lets storage be probed with any `Q` the key borrows as.
+///
+/// `RefNode` can't implement `Borrow<Q>` for every `Q` its key implements it for,
+/// as that overlaps with the blanket `Borrow<T> for T`, so lookups go through this wrapper.
+#[derive(PartialEq, Eq, Hash)]
+#[repr(transparent)]
+struct KeyRef<Q: ?Sized>(Q);
+
+impl<Q: ?Sized> KeyRef<Q> {
+    fn new(key: &Q) -> &Self {
+        // SAFETY: `KeyRef` is a transparent wrapper around `Q`
+        unsafe { &*(key as *const Q as *const Self) }
+    }
+}
+
+impl<K, V, Q> Borrow<KeyRef<Q>> for RefNode<K, V>
+where
+    K: Borrow<Q>,
+    Q: ?Sized,
+{
+    fn borrow(&self) -> &KeyRef<Q> {
+        KeyRef::new(self.key().borrow())
     }
 }
 
@@ -81,23 +186,367 @@ impl<K, V> Clone for RefNode<K, V> {
     }
 }
 
+/// Why `drop_before_insertion` removed an entry
+enum DropReason {
+    HasCollision,
+    FirstInQueue,
+}
+
+/// What inserting a pair did to the cache, see [LRUCache::insert_with_outcome].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertOutcome<K, V> {
+    /// The key was new and there was enough space for it
+    Inserted,
+    /// The key was present, holds the previous pair
+    Replaced(K, V),
+    /// The key was new and the cache was full, holds the pair evicted to make space
+    Evicted(K, V),
+}
+
+/// A single cache operation, see [LRUCache::apply].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op<K, V> {
+    Insert(K, V),
+    Get(K),
+    Remove(K),
+    Resize(NonZeroUsize),
+}
+
+/// Outcome of an [Op], holds what the corresponding method returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpResult<K, V> {
+    Insert(Option<(K, V)>),
+    Get(Option<V>),
+    Remove(Option<V>),
+    Resize(Vec<(K, V)>),
+}
+
+/// Contents of the cache independent of its internals, see [LRUCache::snapshot].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot<K, V> {
+    /// Pairs from the least recently used to the most recently used one.
+    pub entries: Vec<(K, V)>,
+    /// `0` for a disabled cache, see [LRUCache::with_max_len_or_disabled]
+    pub max_len: usize,
+}
+
+/// Error returned when the cache is full and the operation is not allowed to evict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityError;
+
+impl Display for CapacityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("cache is full")
+    }
+}
+
+impl Error for CapacityError {}
+
+/// Error returned by [LRUCache::try_resize_min] when the requested `max_len` is below the floor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeError {
+    pub requested: NonZeroUsize,
+    pub floor: NonZeroUsize,
+}
+
+impl Display for ResizeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "requested max_len {} is below the floor of {}",
+            self.requested, self.floor
+        )
+    }
+}
+
+impl Error for ResizeError {}
+
+/// Broken internal invariant, reported by [LRUCache::checked_insert] instead of panicking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheError {
+    /// More entries are stored than `max_len()` allows
+    LengthExceeded { len: usize, max_len: usize },
+    /// The stored entry is missing from the recency queue
+    Unlinked,
+    /// The least recently used entry is missing from the storage
+    Unstored,
+    /// The cache is full, but the recency queue is empty
+    EmptyQueue,
+    /// The entry is referenced by something besides the storage and the recency queue
+    UnexpectedRefCount(usize),
+}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::LengthExceeded { len, max_len } => {
+                write!(
+                    f,
+                    "cache holds {len} entries, while at most {max_len} are allowed"
+                )
+            }
+            CacheError::Unlinked => f.write_str("stored entry is missing from the recency queue"),
+            CacheError::Unstored => f.write_str("queued entry is missing from the storage"),
+            CacheError::EmptyQueue => f.write_str("cache is full, but the recency queue is empty"),
+            CacheError::UnexpectedRefCount(count) => {
+                write!(f, "entry has {count} references instead of 2")
+            }
+        }
+    }
+}
+
+impl Error for CacheError {}
+
+/// Health report of a cache, see [LRUCache::diagnostics].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheDiagnostics {
+    pub len: usize,
+    pub max_len: usize,
+    /// Number of entries the storage can hold without reallocating
+    pub capacity: usize,
+    /// Number of entries in the recency queue, equals to `len` for a consistent cache
+    pub queue_len: usize,
+    /// Whether the recency queue and the storage hold exactly the same entries
+    pub consistent: bool,
+}
+
+impl Display for CacheDiagnostics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "len {}/{}, capacity {}, queue len {}, {}",
+            self.len,
+            self.max_len,
+            self.capacity,
+            self.queue_len,
+            if self.consistent {
+                "consistent"
+            } else {
+                "inconsistent"
+            }
+        )
+    }
+}
+
+/// Lookup counters of a cache, see [LRUCache::stats].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups that found a valid entry
+    pub hits: u64,
+    /// Lookups that found nothing or an entry failing validation
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Fraction of lookups that were hits, `0.0` if there were none.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+struct PressureHook {
+    threshold: f64,
+    fired: bool,
+    callback: Box<dyn FnMut(usize, usize)>,
+}
+
+impl Debug for PressureHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PressureHook")
+            .field("threshold", &self.threshold)
+            .field("fired", &self.fired)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Why an entry was passed to the listener registered with [LRUCache::on_evict].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// The cache was full when inserting a new key
+    Capacity,
+    /// `max_len()` was shrunk below `len()`
+    Resize,
+    /// Evicted by `evict_until`, `trim_to_bytes` or `repair`
+    Trim,
+    /// Rejected by the read validator or expired, found by a promoting lookup, e.g. `get`
+    Invalid,
+}
+
+struct EvictionListener<K, V> {
+    callback: Box<dyn FnMut(K, V, EvictionReason)>,
+}
+
+impl<K, V> Debug for EvictionListener<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EvictionListener").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EvictionHistory<K> {
+    keys: VecDeque<K>,
+    max_len: usize,
+    clone_key: fn(&K) -> K,
+}
+
+impl<K> EvictionHistory<K> {
+    fn new(max_len: usize) -> Self
+    where
+        K: Clone,
+    {
+        Self {
+            keys: VecDeque::with_capacity(max_len),
+            max_len,
+            clone_key: K::clone,
+        }
+    }
+
+    fn record(&mut self, key: &K) {
+        if self.max_len == 0 {
+            return;
+        }
+        if self.keys.len() == self.max_len {
+            self.keys.pop_front();
+        }
+        self.keys.push_back((self.clone_key)(key));
+    }
+}
+
+type Validator<K, V> = Box<dyn FnMut(&K, &V) -> bool>;
+
+/// Key paired with its rank in the recency queue, `0` being the least recently used.
+pub type RankedKey<'a, K> = (&'a K, usize);
+
+struct ReadValidator<K, V> {
+    is_valid: RefCell<Validator<K, V>>,
+}
+
+impl<K, V> Debug for ReadValidator<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadValidator").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct RankIndex {
+    /// Ranks by node ids, as of the last rebuild
+    ranks: HashMap<usize, usize>,
+    refresh_every: usize,
+    queries_since_rebuild: usize,
+}
+
+/// Reuse distances of retrieved keys, tracked by hashes of the keys
+#[cfg(feature = "profiling")]
+#[derive(Debug, Default, Clone)]
+struct ReuseProfile {
+    /// Every key ever retrieved, from the least recently retrieved to the most recently retrieved one
+    retrieved: Vec<u64>,
+    /// Numbers of retrievals by their reuse distances
+    histogram: Vec<u64>,
+}
+
+#[cfg(feature = "profiling")]
+impl ReuseProfile {
+    fn record<K: Hash + ?Sized>(&mut self, key: &K) {
+        let key_hash = {
+            let mut hasher = std::hash::DefaultHasher::new();
+            key.hash(&mut hasher);
+            std::hash::Hasher::finish(&hasher)
+        };
+
+        if let Some(pos) = self.retrieved.iter().rposition(|&hash| hash == key_hash) {
+            let distance = self.retrieved.len() - 1 - pos;
+            if self.histogram.len() <= distance {
+                self.histogram.resize(distance + 1, 0);
+            }
+            self.histogram[distance] += 1;
+            self.retrieved.remove(pos);
+        }
+        self.retrieved.push(key_hash);
+    }
+}
+
 #[derive(Debug)]
-pub struct LRUCache<K, V> {
-    kv_storage: HashSet<RefNode<K, V>>,
-    recency_queue: RefCell<LinkedList<NodeAdapter<K, V>>>,
-    max_len: NonZeroU32,
+pub struct LRUCache<K, V, S = RandomState, P = Lru> {
+    kv_storage: HashSet<RefNode<K, V>, S>,
+    recency_queue: LinkedList<NodeAdapter<K, V>>,
+    /// [None] for a disabled cache
+    max_len: Option<NonZeroUsize>,
+    insertion_counter: u64,
+    growth_factor: Option<f64>,
+    pressure_hook: Option<PressureHook>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    miss_counter: Option<Rc<Cell<u64>>>,
+    stats: CacheStats,
+    eviction_history: Option<EvictionHistory<K>>,
+    rank_index: Option<RefCell<RankIndex>>,
+    read_validator: Option<ReadValidator<K, V>>,
+    policy: P,
+    #[cfg(feature = "ttl")]
+    ttl: Option<Ttl>,
+    #[cfg(feature = "profiling")]
+    reuse_profile: ReuseProfile,
 }
 
-impl<K, V> Default for LRUCache<K, V> {
+impl<K, V, S, P> Default for LRUCache<K, V, S, P>
+where
+    S: BuildHasher + Default,
+    P: Policy<K, V> + Default,
+{
     fn default() -> Self {
-        let max_len = NonZeroU32::new(1);
-        assert!(max_len.is_some());
+        let max_len = NonZeroUsize::new(1);
+        invariant!(max_len.is_some());
         let max_size = max_len.unwrap();
-        Self {
-            kv_storage: Default::default(),
-            recency_queue: Default::default(),
-            max_len: max_size,
+        Self::with_storage_capacity(Some(max_size), 0, S::default(), P::default())
+    }
+}
+
+/// Copies entries into fresh nodes, keeping `max_len()` and the recency order.
+///
+/// Callbacks can't be cloned, so the copy has no pressure hook, eviction listener,
+/// read validator or expiration policy. The eviction policy is cloned along with its state,
+/// the miss counter is shared with the original.
+impl<K, V, S, P> Clone for LRUCache<K, V, S, P>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher + Clone,
+    P: Policy<K, V> + Clone,
+{
+    fn clone(&self) -> Self {
+        let hasher = self.kv_storage.hasher().clone();
+        let capacity = self.kv_storage.capacity();
+        let mut cache =
+            Self::with_storage_capacity(self.max_len, capacity, hasher, self.policy.clone());
+        for node in self.recency_queue.iter() {
+            let entry = RefNode::new(node.key.clone(), node.value().clone(), node.insertion_seq);
+            entry.ref_count.accesses.set(node.accesses.get());
+            cache.kv_storage.insert(entry.clone());
+            cache.recency_queue.push_back(entry.ref_count);
+        }
+        invariant_eq!(cache.len(), self.len());
+
+        cache.insertion_counter = self.insertion_counter;
+        cache.growth_factor = self.growth_factor;
+        cache.miss_counter = self.miss_counter.clone();
+        cache.stats = self.stats;
+        cache.eviction_history = self.eviction_history.clone();
+        cache.rank_index = self.rank_index.as_ref().map(|rank_index| {
+            // ranks are indexed by node ids, which the copy doesn't share
+            RefCell::new(RankIndex {
+                ranks: HashMap::new(),
+                refresh_every: rank_index.borrow().refresh_every,
+                queries_since_rebuild: 0,
+            })
+        });
+        #[cfg(feature = "profiling")]
+        {
+            cache.reuse_profile = self.reuse_profile.clone();
         }
+        cache
     }
 }
 
@@ -106,203 +555,2002 @@ impl<K, V> LRUCache<K, V> {
         Self::default()
     }
 
+    /// Starts configuring a cache, combining options of the other constructors.
+    pub fn builder() -> LRUCacheBuilder<K, V> {
+        LRUCacheBuilder::new()
+    }
+
     /// Create cache with maximum of `max_size` elements.
     ///
     /// Allocates capacity beforehand.
-    pub fn with_max_len(max_len: NonZeroU32) -> Self {
-        let capacity = max_len.get() as usize;
-        let kv_storage = HashSet::with_capacity(capacity);
-        let recency_queue = RefCell::new(LinkedList::new(NodeAdapter::new()));
+    pub fn with_max_len(max_len: NonZeroUsize) -> Self {
+        Self::with_storage_capacity(Some(max_len), max_len.get(), RandomState::new(), Lru)
+    }
+
+    /// Create cache with maximum of `max_len` elements, or a disabled one if `max_len` is `0`.
+    ///
+    /// A disabled cache never stores anything: `insert` returns the given pair back and lookups miss.
+    /// Methods returning a reference to an inserted value, e.g. `get_or_insert_with`, panic on it,
+    /// since there's no value to refer to. `resize` enables the cache.
+    pub fn with_max_len_or_disabled(max_len: usize) -> Self {
+        match NonZeroUsize::new(max_len) {
+            Some(max_len) => Self::with_max_len(max_len),
+            None => Self::with_storage_capacity(None, 0, RandomState::new(), Lru),
+        }
+    }
+
+    /// Create cache with maximum of `max_len` elements, allocating storage on demand.
+    ///
+    /// The storage starts empty and grows while entries are inserted, rehashing on the way,
+    /// see `with_growth_factor`. Only a cache that never fills up takes less memory
+    /// than a `with_max_len` one, a full cache ends up with the same capacity.
+    pub fn with_max_len_lazy(max_len: NonZeroUsize) -> Self {
+        Self::with_storage_capacity(Some(max_len), 0, RandomState::new(), Lru)
+    }
+
+    /// Create cache with maximum of `max_len` elements, which expire `time_to_live` after insertion.
+    ///
+    /// Expired entries are treated like the ones rejected by a read validator, see
+    /// `with_read_validator`: they're invisible to lookups and removed lazily,
+    /// unless swept with `purge_expired`. Time is read from [SystemClock], see `with_clock`.
+    #[cfg(feature = "ttl")]
+    pub fn with_ttl(max_len: NonZeroUsize, time_to_live: Duration) -> Self {
+        let mut cache = Self::with_max_len(max_len);
+        cache.ttl = Some(Ttl {
+            time_to_live,
+            clock: Box::new(SystemClock),
+        });
+        cache
+    }
+}
+
+impl<K, V, S: BuildHasher> LRUCache<K, V, S> {
+    /// Create cache with maximum of `max_len` elements, hashing keys with `hasher`.
+    ///
+    /// Allocates capacity beforehand, same as `with_max_len`.
+    pub fn with_hasher(max_len: NonZeroUsize, hasher: S) -> Self {
+        Self::with_storage_capacity(Some(max_len), max_len.get(), hasher, Lru)
+    }
+}
+
+impl<K, V, S: BuildHasher, P: Policy<K, V>> LRUCache<K, V, S, P> {
+    // `RefNode` hashes and compares by key only, the intrusive link it carries never affects `Hash`/`Eq`
+    #[allow(clippy::mutable_key_type)]
+    fn with_storage_capacity(
+        max_len: Option<NonZeroUsize>,
+        capacity: usize,
+        hasher: S,
+        policy: P,
+    ) -> Self {
+        let kv_storage = HashSet::with_capacity_and_hasher(capacity, hasher);
+        let recency_queue = LinkedList::new(NodeAdapter::new());
         Self {
             kv_storage,
             recency_queue,
             max_len,
+            insertion_counter: 0,
+            growth_factor: None,
+            pressure_hook: None,
+            eviction_listener: None,
+            miss_counter: None,
+            stats: CacheStats::default(),
+            eviction_history: None,
+            rank_index: None,
+            read_validator: None,
+            policy,
+            #[cfg(feature = "ttl")]
+            ttl: None,
+            #[cfg(feature = "profiling")]
+            reuse_profile: Default::default(),
         }
     }
 
-    /// Adds an element to the queue.
+    /// Makes the storage grow `factor` times whenever it runs out of space, never beyond `max_len()`.
     ///
-    /// If the `key` is new, returns [None] and adds it to cache.
-    /// If `len()` exceeds `max_size()`, the least recently accessed key is removed.
+    /// Matters only for caches not allocated for `max_len()` beforehand, e.g. `new()` or
+    /// `with_max_len_lazy()` ones: bigger steps mean less rehashing while warming up.
+    pub fn with_growth_factor(mut self, factor: f64) -> Self {
+        assert!(factor > 1.0);
+        self.growth_factor = Some(factor);
+        self
+    }
+
+    /// Makes lookups returning [None], promoting ones like `get` and non-promoting ones like `peek`,
+    /// increment `counter`, which may be shared with other caches.
     ///
-    /// If the `key` was present, returns previous key-value pair,
-    /// the key's considered the last used one.
-    pub fn insert(&mut self, key: K, val: V) -> Option<(K, V)>
+    /// Hits don't touch it. Like the cache itself, the counter is not [Send].
+    pub fn with_miss_counter(mut self, counter: Rc<Cell<u64>>) -> Self {
+        self.miss_counter = Some(counter);
+        self
+    }
+
+    /// Makes the cache remember keys of the last `max_len` evicted entries, see `recent_evictions`.
+    pub fn with_eviction_history(mut self, max_len: usize) -> Self
     where
-        K: Hash + Eq + Debug,
-        V: Debug,
+        K: Clone,
     {
-        assert!(self.len() <= self.max_len());
+        self.eviction_history = Some(EvictionHistory::new(max_len));
+        self
+    }
 
-        let removed_val = self.drop_before_insertion(&key);
-        self.push_entry(key, val);
+    /// Makes `rank` answer from an index of all ranks, rebuilt after serving `refresh_every` queries.
+    ///
+    /// Rebuilding walks the whole recency queue, so `rank` costs O(n / refresh_every)
+    /// on average instead of O(n). In exchange ranks may be off by the accesses made
+    /// since the last rebuild: a promoted key keeps its old rank until the next one.
+    /// Keys unknown to the index trigger a rebuild right away.
+    pub fn with_rank_index(mut self, refresh_every: NonZeroUsize) -> Self {
+        self.rank_index = Some(RefCell::new(RankIndex {
+            ranks: HashMap::new(),
+            refresh_every: refresh_every.get(),
+            queries_since_rebuild: 0,
+        }));
+        self
+    }
 
-        assert!(self.len() <= self.max_len());
+    /// Makes the cache evict the victims `policy` chooses instead of the current one,
+    /// see [Policy::choose_victim]. Every eviction goes through it, be it an insertion
+    /// into a full cache, `resize`, `evict_until` or `trim_to_bytes`.
+    ///
+    /// Methods named after recency, e.g. `pop_lru` or `iter_lru`, follow the recency queue,
+    /// which is the eviction order only if `policy` evicts [Victim::LeastRecent].
+    /// `policy` decides whether retrieved entries move to the back of the queue:
+    /// with [Fifo] the least recently used entry is the earliest inserted one.
+    /// Explicit promotions, e.g. `touch` or `insert` of a present key, move entries regardless of it.
+    ///
+    /// Entries are kept along with their recency order, retrieval counts, insertion sequence numbers
+    /// and expiration times, so are stats, listeners and other settings. The state of the current
+    /// policy is dropped: entries already stored are passed to [Policy::on_insert] of `policy`
+    /// in the recency order, e.g. [Lfu] ranks them by retrievals counted so far.
+    pub fn with_policy<P2>(self, mut policy: P2) -> LRUCache<K, V, S, P2>
+    where
+        P2: Policy<K, V>,
+    {
+        Self::replay_entries(&self.recency_queue, &mut policy);
 
-        if let Some(removed_val) = removed_val.as_ref() {
-            assert_eq!(removed_val.strong_ref_count(), 1);
+        LRUCache {
+            kv_storage: self.kv_storage,
+            recency_queue: self.recency_queue,
+            max_len: self.max_len,
+            insertion_counter: self.insertion_counter,
+            growth_factor: self.growth_factor,
+            pressure_hook: self.pressure_hook,
+            eviction_listener: self.eviction_listener,
+            miss_counter: self.miss_counter,
+            stats: self.stats,
+            eviction_history: self.eviction_history,
+            rank_index: self.rank_index,
+            read_validator: self.read_validator,
+            policy,
+            #[cfg(feature = "ttl")]
+            ttl: self.ttl,
+            #[cfg(feature = "profiling")]
+            reuse_profile: self.reuse_profile,
         }
+    }
 
-        removed_val.map(|key_val| key_val.into_pair())
+    /// Replaces the policy with `policy` of the same type, preserving the same as `with_policy`.
+    pub fn set_policy(&mut self, mut policy: P) {
+        Self::replay_entries(&self.recency_queue, &mut policy);
+        self.policy = policy;
     }
 
-    /// Retrieves a value associated with `key`.
-    /// The key is considered most-recently used afterwards
-    pub fn get(&self, key: &K) -> Option<&V>
+    /// Moves entries into a storage hashing keys with `hasher`, e.g. a faster one
+    /// or a DoS-resistant one, preserving the recency order and the rest of the cache.
+    ///
+    /// Takes O(n), every key is hashed again. Storage capacity stays the same.
+    // see `with_storage_capacity` on the key type
+    #[allow(clippy::mutable_key_type)]
+    pub fn rehash_with<S2>(mut self, hasher: S2) -> LRUCache<K, V, S2, P>
     where
         K: Hash + Eq,
+        S2: BuildHasher,
     {
-        self.kv_storage.get(key).map(|entry| {
-            self.drop_from_queue(entry);
-            let mut borrowed_queue = self.recency_queue.borrow_mut();
-            borrowed_queue.push_back(entry.ref_count.clone());
-            entry.value()
-        })
+        let mut kv_storage = HashSet::with_capacity_and_hasher(self.kv_storage.capacity(), hasher);
+        self.kv_storage.clear();
+
+        let mut cursor = self.recency_queue.front();
+        while let Some(node) = cursor.clone_pointer() {
+            invariant_eq!(Rc::strong_count(&node), 2);
+            kv_storage.insert(RefNode { ref_count: node });
+            cursor.move_next();
+        }
+        invariant_eq!(kv_storage.len(), self.recency_queue.iter().count());
+
+        LRUCache {
+            kv_storage,
+            recency_queue: self.recency_queue,
+            max_len: self.max_len,
+            insertion_counter: self.insertion_counter,
+            growth_factor: self.growth_factor,
+            pressure_hook: self.pressure_hook,
+            eviction_listener: self.eviction_listener,
+            miss_counter: self.miss_counter,
+            stats: self.stats,
+            eviction_history: self.eviction_history,
+            rank_index: self.rank_index,
+            read_validator: self.read_validator,
+            policy: self.policy,
+            #[cfg(feature = "ttl")]
+            ttl: self.ttl,
+            #[cfg(feature = "profiling")]
+            reuse_profile: self.reuse_profile,
+        }
     }
 
-    pub fn max_len(&self) -> usize {
-        let as_usize = self.max_len.get() as usize;
-        assert!(self.len() <= as_usize);
-        as_usize
+    /// Passes stored entries to `policy` as if they were inserted in the recency order
+    fn replay_entries<P2: Policy<K, V>>(queue: &LinkedList<NodeAdapter<K, V>>, policy: &mut P2) {
+        for node in queue.iter() {
+            policy.on_insert(PolicyNode::new(node));
+        }
     }
 
-    pub fn len(&self) -> usize {
-        self.kv_storage.len()
+    /// Makes lookups check found entries with `is_valid`, treating the ones it rejects as absent.
+    ///
+    /// Rejected entries are removed lazily: `get` and `get_mut` remove the one they find,
+    /// passing it to the listener registered with `on_evict`, see [EvictionReason::Invalid].
+    /// Non-promoting lookups, e.g. `peek`, leave it in place, counted by `len()`
+    /// until it's evicted, replaced or found by a promoting lookup.
+    /// `is_valid` must not access the cache.
+    pub fn with_read_validator<F>(mut self, is_valid: F) -> Self
+    where
+        F: FnMut(&K, &V) -> bool + 'static,
+    {
+        self.read_validator = Some(ReadValidator {
+            is_valid: RefCell::new(Box::new(is_valid)),
+        });
+        self
     }
 
-    pub fn resize(&mut self, new_max_len: NonZeroU32) -> Vec<(K, V)>
+    /// Makes expiration of entries read time from `clock`, see `with_ttl`.
+    ///
+    /// Does nothing for caches created without a time-to-live.
+    #[cfg(feature = "ttl")]
+    pub fn with_clock<C>(mut self, clock: C) -> Self
     where
-        K: Hash + Eq + Debug,
-        V: Debug,
+        C: Clock + 'static,
     {
-        if new_max_len >= self.max_len {
-            self.kv_storage
-                .reserve(new_max_len.get() as usize - self.max_len());
-            self.max_len = new_max_len;
-            return Vec::new();
+        if let Some(ttl) = self.ttl.as_mut() {
+            ttl.clock = Box::new(clock);
         }
+        self
+    }
 
-        if self.kv_storage.is_empty() {
-            return Vec::new();
+    /// Adds an element to the queue.
+    ///
+    /// If the `key` is new, returns [None] and adds it to cache.
+    /// If `len()` exceeds `max_size()`, the policy's victim is removed, see [Policy::choose_victim].
+    ///
+    /// If the `key` was present, returns previous key-value pair,
+    /// the key's considered the last used one.
+    pub fn insert(&mut self, key: K, val: V) -> Option<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        if self.max_len.is_none() {
+            // consumed like by a stored entry, so it's never reused
+            self.insertion_counter += 1;
+            return Some((key, val));
         }
 
-        let mut borrowed_queue = self.recency_queue.borrow_mut();
+        match self.insert_with_outcome(key, val) {
+            InsertOutcome::Replaced(key, val) => Some((key, val)),
+            InsertOutcome::Inserted | InsertOutcome::Evicted(..) => None,
+        }
+    }
 
-        let mut all_removed = Vec::new();
-        for _ in new_max_len.get() as usize..self.len() {
-            let removed = borrowed_queue.pop_front();
-            assert!(removed.is_some());
-            let removed = RefNode {
-                ref_count: removed.unwrap(),
-            };
+    /// Same as `insert`, but tells a replaced pair from the one evicted to make space.
+    ///
+    /// The evicted pair is returned, unless a listener is registered with `on_evict`.
+    /// A disabled cache evicts the given pair right away.
+    pub fn insert_with_outcome(&mut self, key: K, val: V) -> InsertOutcome<K, V>
+    where
+        K: Hash + Eq,
+    {
+        invariant!(self.len() <= self.max_len());
+        if self.max_len.is_none() {
+            // consumed like by a stored entry, so it's never reused
+            self.insertion_counter += 1;
+            return InsertOutcome::Evicted(key, val);
+        }
 
-            let was_removed = self.kv_storage.remove(removed.key());
-            assert!(was_removed);
-            assert_eq!(removed.strong_ref_count(), 1);
+        let dropped = self.make_room(&key);
+        let accesses = match &dropped {
+            // replacing a value counts as a retrieval of the key
+            Some((replaced, DropReason::HasCollision)) => replaced.ref_count.accesses.get() + 1,
+            _ => 0,
+        };
+        self.push_counted_entry(key, val, accesses);
 
-            all_removed.push(removed.into_pair());
-        }
-        self.max_len = new_max_len;
-        all_removed
+        let outcome = match dropped {
+            None => InsertOutcome::Inserted,
+            Some((replaced, DropReason::HasCollision)) => {
+                invariant_eq!(replaced.strong_ref_count(), 1);
+                let (key, val) = replaced.into_pair();
+                InsertOutcome::Replaced(key, val)
+            }
+            Some((evicted, DropReason::FirstInQueue)) => {
+                match self.notify_evicted(evicted, EvictionReason::Capacity) {
+                    Some((key, val)) => InsertOutcome::Evicted(key, val),
+                    None => InsertOutcome::Inserted,
+                }
+            }
+        };
+
+        invariant!(self.len() <= self.max_len());
+        self.check_pressure();
+        outcome
     }
 
-    /// Iterate over elements in an unspecified order.
-    /// Does not affect order of elements removal.
-    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)>
+    /// Same as `insert`, but returns a reference to the inserted value instead of the replaced pair,
+    /// sparing a lookup to read it back.
+    ///
+    /// Panics on a disabled cache, since there's no value to refer to.
+    pub fn insert_and_get(&mut self, key: K, val: V) -> &V
     where
         K: Hash + Eq,
     {
-        self.kv_storage
-            .iter()
-            .map(|elem| (elem.key(), elem.value()))
+        assert!(self.max_len.is_some(), "disabled cache can't store values");
+        self.insert(key, val);
+        self.back_entry().value()
     }
 
-    /// If key is present in storage, remove it from queue and storage and return removed node.
-    ///
-    /// If `len()` equals to `max_size()`, drop the first value from queue and storage and return [None].
+    /// Inserts the pair only if `key` is absent, returning a reference to the inserted value.
     ///
-    /// Just return [None] otherwise.
+    /// Otherwise leaves the stored value intact, making the key the last used one like `touch`,
+    /// and returns the given pair back. Entries rejected by the read validator or expired ones
+    /// count as absent and are replaced. Panics on a disabled cache, like `insert_and_get`.
+    pub fn try_insert(&mut self, key: K, val: V) -> Result<&V, (K, V)>
+    where
+        K: Hash + Eq,
+    {
+        if self.touch(&key) {
+            return Err((key, val));
+        }
+        Ok(self.insert_and_get(key, val))
+    }
+
+    /// Same as `insert`, but checks invariants it relies on beforehand, returning [CacheError]
+    /// instead of panicking if any of them is broken. The cache is left untouched then.
+    /// A disabled cache has no invariants to break, the pair is returned back like by `insert`.
     ///
-    /// Cache has a place to insert new entry.after call
-    fn drop_before_insertion(&mut self, key: &K) -> Option<RefNode<K, V>>
+    /// - [CacheError::LengthExceeded] if `len()` exceeds `max_len()`
+    /// - [CacheError::Unlinked] if the entry of present `key` is not in the recency queue
+    /// - [CacheError::EmptyQueue] if a new key requires eviction, but the queue is empty
+    /// - [CacheError::Unstored] if the entry to be evicted is not in the storage
+    /// - [CacheError::UnexpectedRefCount] if the entry to be replaced or evicted
+    ///   is referenced by something besides the storage and the queue
+    pub fn checked_insert(&mut self, key: K, val: V) -> Result<Option<(K, V)>, CacheError>
     where
         K: Hash + Eq,
     {
-        enum DropReason {
-            HasCollision,
-            FirstInQueue,
+        if self.max_len.is_none() {
+            // nothing is stored, so there's nothing to check
+            return Ok(self.insert(key, val));
         }
 
-        let init_len = self.len();
-        let (to_remove, reason) = match self.kv_storage.get(key) {
-            Some(to_remove) => {
-                self.drop_from_queue(to_remove);
-                (to_remove.clone(), DropReason::HasCollision)
-            }
-            None if self.len() == self.max_len() => {
-                let to_remove = self.recency_queue.borrow_mut().front_mut().remove();
-                // since [max_size] is not less than 1, there is at least one element in the queue,
-                //   thus, we've removed something
-                assert!(to_remove.is_some());
-                (
-                    RefNode {
-                        ref_count: to_remove.unwrap(),
-                    },
-                    DropReason::FirstInQueue,
-                )
+        let (len, max_len) = (self.len(), self.max_len());
+        if len > max_len {
+            return Err(CacheError::LengthExceeded { len, max_len });
+        }
+
+        if let Some(entry) = self.kv_storage.get(KeyRef::new(&key)) {
+            if !entry.ref_count.link.is_linked() {
+                return Err(CacheError::Unlinked);
             }
-            None => {
-                assert!(self.len() < self.max_len());
-                return None;
+            Self::check_ref_count(entry)?;
+        } else if len == max_len {
+            let nodes = Nodes::new(self.recency_queue.iter());
+            let victim = self.policy.choose_victim(nodes);
+            let victim = Self::find_victim(&self.recency_queue, &self.kv_storage, victim);
+            let Some(victim) = victim else {
+                return Err(CacheError::EmptyQueue);
+            };
+            let Some(entry) = self.kv_storage.get(KeyRef::new(&victim.key)) else {
+                return Err(CacheError::Unstored);
+            };
+            if !ptr::eq(entry.ref_count.deref(), victim) {
+                return Err(CacheError::Unstored);
             }
-        };
-
-        let was_removed = self.kv_storage.remove(to_remove.key());
+            Self::check_ref_count(entry)?;
+        }
 
-        assert!(was_removed);
+        Ok(self.insert(key, val))
+    }
 
-        assert_eq!(init_len - 1, self.len());
-        assert!(self.len() < self.max_len());
+    /// Same as `insert`, but instead of evicting grows `max_len()` by one, up to `max_allowed`.
+    ///
+    /// Once `max_len()` reaches `max_allowed`, the policy's victim is evicted
+    /// and returned, unless a listener is registered with `on_evict`.
+    /// If the `key` was present, returns previous key-value pair as `insert` does.
+    pub fn insert_growing(&mut self, key: K, val: V, max_allowed: NonZeroUsize) -> Option<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        let Some(max_len) = self.max_len else {
+            return self.insert(key, val);
+        };
+        let is_full = self.len() == max_len.get();
+        if !is_full || self.kv_storage.contains(KeyRef::new(&key)) {
+            return self.insert(key, val);
+        }
 
-        match reason {
-            DropReason::HasCollision => Some(to_remove),
-            DropReason::FirstInQueue => None,
+        if max_len < max_allowed {
+            self.max_len = Some(max_len.saturating_add(1));
+            self.kv_storage.reserve(1);
+            return self.insert(key, val);
         }
+
+        let evicted = self.evict_node();
+        invariant!(evicted.is_some());
+        let replaced = self.insert(key, val);
+        invariant!(replaced.is_none());
+        self.notify_evicted(evicted?, EvictionReason::Capacity)
     }
 
-    fn drop_from_queue(&self, entry: &RefNode<K, V>) {
-        assert!(entry.ref_count.link.is_linked());
-        assert_eq!(entry.strong_ref_count(), 2);
-        {
-            let mut borrowed_queue = self.recency_queue.borrow_mut();
-            let mut entry_cursor =
-                unsafe { borrowed_queue.cursor_mut_from_ptr(entry.ref_count.deref()) };
-            entry_cursor.remove();
-        }
-        assert_eq!(entry.strong_ref_count(), 1);
+    /// Inserts the pair as `insert` does, returning a guard which removes it when dropped,
+    /// unless `EvictOnDrop::keep` is called.
+    ///
+    /// The cache is accessible through the guard meanwhile. If the entry is replaced or evicted
+    /// before the guard is dropped, the guard leaves the cache as is. A pair replaced by
+    /// this insertion is dropped.
+    pub fn scoped_insert(&mut self, key: K, val: V) -> EvictOnDrop<'_, K, V, S, P>
+    where
+        K: Hash + Eq + Clone,
+    {
+        let guarded_key = key.clone();
+        let insertion_seq = self.insertion_counter;
+        self.insert(key, val);
+        EvictOnDrop::new(self, guarded_key, insertion_seq)
     }
 
-    /// Requires Cache to have free space for insertion
-    /// Puts new key-value pair, pushes `key` to the end of the queue
-    fn push_entry(&mut self, key: K, val: V)
+    /// Same as `insert`, but if `key` is present with a value equal to `val`,
+    /// the key's only made the last used one, without reallocating its entry.
+    /// Entries rejected by the read validator or expired ones are replaced as by `insert`.
+    ///
+    /// In that case the given pair, equal to the stored one, is returned back.
+    pub fn insert_dedup(&mut self, key: K, val: V) -> Option<(K, V)>
     where
         K: Hash + Eq,
+        V: PartialEq,
     {
-        assert!(self.len() < self.max_len());
-
-        let entry = RefNode::new(key, val);
-        assert_eq!(entry.strong_ref_count(), 1);
+        match self.kv_storage.get(KeyRef::new(&key)) {
+            Some(entry) if *entry.value() == val && self.is_valid(entry) => {
+                Self::move_to_back(&mut self.recency_queue, entry);
+                Some((key, val))
+            }
+            _ => self.insert(key, val),
+        }
+    }
 
-        self.kv_storage.insert(entry.clone());
-        self.recency_queue.borrow_mut().push_back(entry.ref_count);
+    /// Retrieves a value associated with `key`, calling `f` to insert it on a miss, unless the cache is full.
+    ///
+    /// On a hit the key's considered the last used one. On a miss with `len() == max_len()`
+    /// nothing is evicted, `f` is not called and [CapacityError] is returned.
+    pub fn get_or_insert_checked<F>(&mut self, key: K, f: F) -> Result<&V, CapacityError>
+    where
+        K: Hash + Eq,
+        F: FnOnce() -> V,
+    {
+        if self.get(&key).is_some() {
+            let found = self.kv_storage.get(KeyRef::new(&key));
+            invariant!(found.is_some());
+            return Ok(found.unwrap().value());
+        }
+        // `get` removed an entry failing validation, so the key is absent and needs a free slot
+        if self.len() == self.max_len() {
+            return Err(CapacityError);
+        }
+
+        self.push_entry(key, f());
+        self.check_pressure();
+        Ok(self.back_entry().value())
+    }
+
+    /// Returns a clone of the value associated with `key`, calling `f` to insert it on a miss.
+    ///
+    /// Either way the key's considered the last used one, a miss may evict like `insert`.
+    /// Unlike returning a reference, the result doesn't keep the cache borrowed.
+    pub fn get_cloned_or_insert_with<F>(&mut self, key: K, f: F) -> V
+    where
+        K: Hash + Eq,
+        V: Clone,
+        F: FnOnce() -> V,
+    {
+        if let Some(found) = self.get(&key) {
+            return found.clone();
+        }
+
+        let val = f();
+        let inserted = val.clone();
+        self.insert(key, val);
+        inserted
+    }
+
+    /// Registers `callback` to be called with `(len(), max_len())`, when `insert`
+    /// makes `len() / max_len()` reach `threshold`.
+    ///
+    /// It's called once per crossing: after firing it's not called again until
+    /// an insertion observes the fraction below `threshold`, e.g. after a `resize`.
+    /// Replaces the previously registered callback.
+    pub fn on_pressure<F>(&mut self, threshold: f64, callback: F)
+    where
+        F: FnMut(usize, usize) + 'static,
+    {
+        assert!((0.0..=1.0).contains(&threshold));
+        self.pressure_hook = Some(PressureHook {
+            threshold,
+            fired: false,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Registers `callback` to take every pair evicted to make room for a new key,
+    /// to fit a shrunk `max_len()` or by `evict_until` and `trim_to_bytes`, see [EvictionReason].
+    ///
+    /// Pairs replaced by inserting the same key, or removed explicitly, e.g. by `remove` or `pop_lru`,
+    /// are not passed to it. While it's registered, methods returning evicted pairs, e.g. `resize`
+    /// or `insert_growing`, hand them to it instead. Replaces the previously registered callback.
+    pub fn on_evict<F>(&mut self, callback: F)
+    where
+        F: FnMut(K, V, EvictionReason) + 'static,
+    {
+        self.eviction_listener = Some(EvictionListener {
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Retrieves a value associated with `key`.
+    /// The key is considered most-recently used afterwards
+    ///
+    /// Relinking the entry changes the recency queue, so the cache is borrowed uniquely,
+    /// see `peek` for a lookup through a shared reference.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        #[cfg(feature = "profiling")]
+        self.reuse_profile.record(key);
+
+        self.lookup(key).map(RefNode::value)
+    }
+
+    /// Retrieves a mutable reference to the value associated with `key`.
+    /// The key is considered most-recently used afterwards
+    ///
+    /// Only the value can be changed through the reference: keys are never handed out mutably,
+    /// since changing one would break its position in storage.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        #[cfg(feature = "profiling")]
+        self.reuse_profile.record(key);
+
+        let found = self.lookup(key)?;
+        Some(unsafe { found.value_mut() })
+    }
+
+    /// Retrieves mutable references to values associated with all `keys` at once,
+    /// which are considered most-recently used afterwards, in the given order.
+    ///
+    /// Returns [None] if any key is absent or repeated, leaving the cache untouched then.
+    pub fn get_many_mut<Q, const N: usize>(&mut self, keys: [&Q; N]) -> Option<[&mut V; N]>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        for (i, key) in keys.iter().enumerate() {
+            self.kv_storage
+                .get(KeyRef::new(*key))
+                .filter(|entry| self.is_valid(entry))?;
+            if keys[..i].contains(key) {
+                return None;
+            }
+        }
+
+        for key in keys {
+            #[cfg(feature = "profiling")]
+            self.reuse_profile.record(key);
+
+            let found = self.lookup(key);
+            invariant!(found.is_some());
+        }
+        // keys are distinct, so are the entries
+        Some(keys.map(|key| {
+            let entry = self.kv_storage.get(KeyRef::new(key));
+            invariant!(entry.is_some());
+            unsafe { entry.unwrap().value_mut() }
+        }))
+    }
+
+    /// Checks whether `key` is stored.
+    /// Does not affect order of elements removal.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.kv_storage.contains(KeyRef::new(key))
+    }
+
+    /// Retrieves a value associated with `key`, if there is one.
+    /// Does not affect order of elements removal.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let found = self
+            .kv_storage
+            .get(KeyRef::new(key))
+            .filter(|entry| self.is_valid(entry));
+        if found.is_none() {
+            self.count_miss();
+        }
+        found.map(|entry| entry.value())
+    }
+
+    /// Retrieves a value associated with `key`, or `default` if it's absent.
+    /// Does not affect order of elements removal.
+    pub fn peek_or<'a, Q>(&'a self, key: &Q, default: &'a V) -> &'a V
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).unwrap_or(default)
+    }
+
+    /// Returns a mutable reference to the value associated with `key`, inserting `V::default()` on a miss.
+    ///
+    /// Unlike other retrieving methods, a hit does not make the key the last used one,
+    /// so updating an entry doesn't extend its lifetime in the cache. A miss inserts
+    /// the default value as `insert` does, evicting if `len()` reaches `max_len()`.
+    /// Entries rejected by the read validator or expired ones are replaced the same way.
+    pub fn get_mut_or_default_no_promote(&mut self, key: K) -> &mut V
+    where
+        K: Hash + Eq,
+        V: Default,
+    {
+        let is_hit = self
+            .kv_storage
+            .get(KeyRef::new(&key))
+            .is_some_and(|entry| self.is_valid(entry));
+        if !is_hit {
+            self.drop_before_insertion(&key);
+            self.push_entry(key, V::default());
+            self.check_pressure();
+            return unsafe { self.back_entry().value_mut() };
+        }
+
+        let found = self.kv_storage.get(KeyRef::new(&key));
+        invariant!(found.is_some());
+        unsafe { found.unwrap().value_mut() }
+    }
+
+    /// Checks whether `key` is present and its entry is usable, i.e. accepted by the read validator.
+    ///
+    /// Only reports: a rejected entry is neither evicted nor moved, see `with_read_validator`.
+    /// Without a validator it just checks presence. Does not affect order of elements removal.
+    pub fn contains_key_fresh<Q>(&self, key: &Q) -> bool
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.kv_storage
+            .get(KeyRef::new(key))
+            .is_some_and(|entry| self.is_valid(entry))
+    }
+
+    /// Retrieves clones of the stored key and the value associated with `key`.
+    /// The key is considered most-recently used afterwards
+    ///
+    /// The stored key is the one the entry was inserted with, which may differ from
+    /// the equal `key` used for lookup, e.g. with case-insensitive [Eq].
+    pub fn get_entry_cloned<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Hash + Eq + Borrow<Q> + Clone,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        let val = self.get(key)?.clone();
+        let stored = self.kv_storage.get(KeyRef::new(key));
+        invariant!(stored.is_some());
+        Some((stored.unwrap().key().clone(), val))
+    }
+
+    /// Retrieves clones of values associated with `keys`, [None] for absent ones.
+    ///
+    /// Present keys are made the last used ones in the order of `keys`,
+    /// so the last present one ends up the most recently used.
+    pub fn get_many_cloned(&mut self, keys: &[K]) -> Vec<Option<V>>
+    where
+        K: Hash + Eq,
+        V: Clone,
+    {
+        keys.iter().map(|key| self.get(key).cloned()).collect()
+    }
+
+    /// Gets the entry of `key` for in-place manipulation.
+    ///
+    /// Looks the key up like `get`: a stored key is considered the last used one right away,
+    /// accessing the occupied entry afterwards, e.g. with [OccupiedEntry::get], doesn't promote it
+    /// again. An absent key is promoted only once a value is inserted into the vacant entry.
+    /// An entry rejected by the read validator or an expired one is removed, so the entry is vacant.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S, P>
+    where
+        K: Hash + Eq,
+    {
+        #[cfg(feature = "profiling")]
+        self.reuse_profile.record(&key);
+
+        if self.lookup(&key).is_none() {
+            return Entry::Vacant(VacantEntry::new(self, key));
+        }
+
+        let found = self.kv_storage.get(KeyRef::new(&key));
+        invariant!(found.is_some());
+        let node = Rc::as_ptr(&found.unwrap().ref_count);
+        Entry::Occupied(OccupiedEntry::new(self, node))
+    }
+
+    /// Retrieves a value associated with `key`, inserting `default` if it's absent.
+    /// The key is considered most-recently used afterwards
+    ///
+    /// Inserting may evict like `insert`, see `get_or_insert_with` to compute the value only on a miss.
+    pub fn get_or_insert(&mut self, key: K, default: V) -> &V
+    where
+        K: Hash + Eq,
+    {
+        self.entry(key).or_insert(default)
+    }
+
+    /// Retrieves a value associated with `key`, inserting `f()` if it's absent.
+    /// The key is considered most-recently used afterwards
+    ///
+    /// `f` is called only on a miss, inserting may evict like `insert`.
+    pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> &V
+    where
+        K: Hash + Eq,
+        F: FnOnce() -> V,
+    {
+        self.entry(key).or_insert_with(f)
+    }
+
+    /// Same as `get_or_insert_with`, but `f` may fail, in which case its error is returned.
+    ///
+    /// Nothing is evicted until `f` produces a value, so a failed computation leaves the cache intact.
+    pub fn get_or_try_insert_with<F, E>(&mut self, key: K, f: F) -> Result<&V, E>
+    where
+        K: Hash + Eq,
+        F: FnOnce() -> Result<V, E>,
+    {
+        match self.entry(key) {
+            Entry::Occupied(occupied) => Ok(occupied.into_mut()),
+            Entry::Vacant(vacant) => Ok(vacant.insert(f()?)),
+        }
+    }
+
+    /// Applies `modify` to the value associated with `key`, or inserts `create()` if it's absent,
+    /// returning the resident value.
+    ///
+    /// Either way the key's considered the last used one, inserting may evict like `insert`.
+    pub fn upsert<F, G>(&mut self, key: K, modify: F, create: G) -> &V
+    where
+        K: Hash + Eq,
+        F: FnOnce(&mut V),
+        G: FnOnce() -> V,
+    {
+        self.entry(key).and_modify(modify).or_insert_with(create)
+    }
+
+    /// Moves present `key` to position `rank` in the recency order, 0 being the least recently used one.
+    ///
+    /// `rank` is clamped to `len() - 1`, the most recently used position.
+    /// Returns `false` if `key` is absent.
+    pub fn move_to_rank<Q>(&mut self, key: &Q, rank: usize) -> bool
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let rank = rank.min(self.len().saturating_sub(1));
+        let Some(entry) = self.kv_storage.get(KeyRef::new(key)) else {
+            return false;
+        };
+
+        let queue = &mut self.recency_queue;
+        Self::unlink(queue, entry);
+
+        let mut cursor = queue.front_mut();
+        for _ in 0..rank {
+            cursor.move_next();
+        }
+        // on reaching the end the cursor points at null, which inserts to the back
+        cursor.insert_before(entry.ref_count.clone());
+        true
+    }
+
+    /// Makes `key` the last used one without retrieving its value.
+    /// Returns `false` if there's no such key.
+    ///
+    /// Unlike `get`, doesn't count as a hit or a miss.
+    /// Entries rejected by the read validator or expired ones are left in place.
+    pub fn touch<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let found = self.kv_storage.get(KeyRef::new(key));
+        let Some(entry) = found.filter(|entry| self.is_valid(entry)) else {
+            return false;
+        };
+        Self::move_to_back(&mut self.recency_queue, entry);
+        true
+    }
+
+    /// Makes every key matching `f` the last used one, keeping their relative recency order.
+    /// Returns the number of promoted keys.
+    pub fn touch_matching<F>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let queue = &mut self.recency_queue;
+        let mut matched = Vec::new();
+        let mut cursor = queue.front_mut();
+        while let Some(node) = cursor.get() {
+            if f(&node.key) {
+                matched.extend(cursor.remove());
+            } else {
+                cursor.move_next();
+            }
+        }
+
+        let promoted = matched.len();
+        for node in matched {
+            queue.push_back(node);
+        }
+        promoted
+    }
+
+    /// Returns position of `key` in the recency order, 0 being the least recently used one,
+    /// or [None] if it's absent.
+    ///
+    /// Walks the recency queue, so it's O(n). Does not affect order of elements removal.
+    pub fn rank_exact<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.kv_storage.get(KeyRef::new(key))?;
+        let rank = self
+            .recency_queue
+            .iter()
+            .position(|node| ptr::eq(node, entry.ref_count.deref()));
+        invariant!(rank.is_some());
+        rank
+    }
+
+    /// Returns approximate position of `key` in the recency order, 0 being the least recently used one,
+    /// or [None] if it's absent.
+    ///
+    /// Same as `rank_exact` unless the cache was created `with_rank_index`, see it for the accuracy.
+    /// Does not affect order of elements removal.
+    pub fn rank<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(rank_index) = self.rank_index.as_ref() else {
+            return self.rank_exact(key);
+        };
+        let node_id = self.node_id(key)?;
+        let mut rank_index = rank_index.borrow_mut();
+
+        let is_stale = rank_index.queries_since_rebuild >= rank_index.refresh_every;
+        if is_stale || !rank_index.ranks.contains_key(&node_id) {
+            let ranks = self
+                .recency_queue
+                .iter()
+                .enumerate()
+                .map(|(rank, node)| (node as *const Node<K, V> as usize, rank))
+                .collect();
+            rank_index.ranks = ranks;
+            rank_index.queries_since_rebuild = 0;
+        }
+        rank_index.queries_since_rebuild += 1;
+
+        let rank = rank_index.ranks.get(&node_id).copied();
+        invariant!(rank.is_some());
+        rank.map(|rank| rank.min(self.len() - 1))
+    }
+
+    /// Reorders entries to follow the recency order of `other`, contents are not changed.
+    ///
+    /// Keys present in both caches become the least recently used ones, ordered as in `other`.
+    /// Keys absent from `other` follow them, as the most recently used ones, keeping
+    /// their relative order. Keys only present in `other` are ignored.
+    pub fn adopt_recency_from(&mut self, other: &LRUCache<K, V, S, P>)
+    where
+        K: Hash + Eq,
+    {
+        let queue = &mut self.recency_queue;
+        let mut adopted = Vec::new();
+        for other_node in other.recency_queue.iter() {
+            if let Some(entry) = self.kv_storage.get(KeyRef::new(&other_node.key)) {
+                Self::unlink(queue, entry);
+                adopted.push(entry.ref_count.clone());
+            }
+        }
+
+        for node in adopted.into_iter().rev() {
+            queue.push_front(node);
+        }
+    }
+
+    /// Returns the insertion sequence number of `key`, or [None] if it's absent.
+    ///
+    /// Every insertion gets a number bigger than all the previous ones, re-inserting
+    /// a present key stamps it anew. Unlike recency, it's not affected by `get`,
+    /// nor does this call affect order of elements removal.
+    pub fn insertion_seq<Q>(&self, key: &Q) -> Option<u64>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.kv_storage
+            .get(KeyRef::new(key))
+            .map(|entry| entry.ref_count.insertion_seq)
+    }
+
+    /// Returns an id of the node storing `key`, or [None] if it's absent.
+    ///
+    /// The id is the node's address, it stays the same while the entry is resident
+    /// and may be reused by another entry after this one is evicted or replaced.
+    /// Does not affect order of elements removal.
+    pub fn node_id<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.kv_storage
+            .get(KeyRef::new(key))
+            .map(|entry| Rc::as_ptr(&entry.ref_count) as usize)
+    }
+
+    pub fn max_len(&self) -> usize {
+        let as_usize = self.max_len.map_or(0, NonZeroUsize::get);
+        invariant!(self.len() <= as_usize);
+        as_usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.kv_storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kv_storage.is_empty()
+    }
+
+    /// Number of entries the storage can hold without reallocating.
+    ///
+    /// Unrelated to `max_len()`: it may be lower for caches not allocated beforehand,
+    /// or higher after `reserve` or shrinking with `resize`.
+    pub fn capacity(&self) -> usize {
+        self.kv_storage.capacity()
+    }
+
+    /// Returns `(len(), max_len(), capacity())` at once.
+    pub fn len_and_capacity(&self) -> (usize, usize, usize) {
+        let len = self.len();
+        let max_len = self.max_len.map_or(0, NonZeroUsize::get);
+        invariant!(len <= max_len);
+        (len, max_len, self.capacity())
+    }
+
+    /// Changes `max_len()`, evicting the policy's victims until the rest fits, see [Policy::choose_victim].
+    ///
+    /// Returns evicted pairs, unless a listener is registered with `on_evict`.
+    pub fn resize(&mut self, new_max_len: NonZeroUsize) -> Vec<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        self.resize_drain(new_max_len).collect()
+    }
+
+    /// Changes `max_len()` like `resize`, but yields evicted pairs lazily,
+    /// in the order the policy chooses them as victims.
+    ///
+    /// `max_len()` is updated right away, pairs not consumed are evicted when the iterator is dropped.
+    /// Nothing is yielded while a listener is registered with `on_evict`, it receives the pairs instead.
+    pub fn resize_drain(&mut self, new_max_len: NonZeroUsize) -> ResizeDrain<'_, K, V, S, P>
+    where
+        K: Hash + Eq,
+    {
+        if Some(new_max_len) > self.max_len {
+            self.kv_storage.reserve(new_max_len.get() - self.max_len());
+        }
+        let excess = self.len().saturating_sub(new_max_len.get());
+        self.max_len = Some(new_max_len);
+        ResizeDrain {
+            cache: self,
+            excess,
+        }
+    }
+
+    /// Evicts the policy's victims until `stop` returns `true` or the cache is empty,
+    /// see [Policy::choose_victim].
+    ///
+    /// `stop` is checked before every eviction and sees the cache as it is between them.
+    /// Evicted pairs are passed to the listener registered with `on_evict`, if there's one.
+    /// Returns the number of evicted entries.
+    pub fn evict_until<F>(&mut self, mut stop: F) -> usize
+    where
+        K: Hash + Eq,
+        F: FnMut(&Self) -> bool,
+    {
+        let mut evicted = 0;
+        while !self.kv_storage.is_empty() && !stop(self) {
+            let removed = self.evict_node();
+            invariant!(removed.is_some());
+            if let Some(removed) = removed {
+                self.notify_evicted(removed, EvictionReason::Trim);
+            }
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Returns the least recently used key with its rank `0`
+    /// and the most recently used key with its rank `len() - 1`,
+    /// or [None] if the cache is empty.
+    /// Does not affect order of elements removal.
+    pub fn recency_bounds(&self) -> Option<(RankedKey<'_, K>, RankedKey<'_, K>)>
+    where
+        K: Hash + Eq,
+    {
+        if self.kv_storage.is_empty() {
+            return None;
+        }
+        let oldest = self.front_entry().key();
+        let newest = self.back_entry().key();
+        Some(((oldest, 0), (newest, self.len() - 1)))
+    }
+
+    /// Removes all entries, keeping `max_len()` and the allocated storage.
+    pub fn clear(&mut self)
+    where
+        K: Hash + Eq,
+    {
+        // nodes are unlinked before storage drops them
+        while self.pop_front_node().is_some() {}
+        invariant!(self.kv_storage.is_empty());
+        self.check_pressure();
+    }
+
+    /// Removes all entries, returning them from the least recently used to the most recently used one.
+    ///
+    /// Keeps `max_len()` and the allocated storage, like `clear`.
+    pub fn drain(&mut self) -> Drain<'_, K, V, S, P>
+    where
+        K: Hash + Eq,
+    {
+        Drain { cache: self }
+    }
+
+    /// Retrieves the least recently used pair, or [None] if the cache is empty.
+    /// Does not affect order of elements removal.
+    ///
+    /// It's the front of the recency queue, which is the next one to be evicted only
+    /// with policies evicting [Victim::LeastRecent], e.g. the default [Lru] one.
+    pub fn peek_lru(&self) -> Option<(&K, &V)>
+    where
+        K: Hash + Eq,
+    {
+        if self.kv_storage.is_empty() {
+            return None;
+        }
+        let entry = self.front_entry();
+        Some((entry.key(), entry.value()))
+    }
+
+    /// Removes the least recently used pair and returns it, or [None] if the cache is empty.
+    pub fn pop_lru(&mut self) -> Option<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        self.pop_front_node().map(RefNode::into_pair)
+    }
+
+    /// Removes the most recently used pair and returns it, or [None] if the cache is empty.
+    pub fn pop_mru(&mut self) -> Option<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        self.pop_node(Victim::MostRecent).map(RefNode::into_pair)
+    }
+
+    /// Removes `key`, returning its value, or [None] if `key` is absent.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (_, val) = self.take_entry(key)?.into_pair();
+        Some(val)
+    }
+
+    /// Removes `key`, returning its pair and the key used right after it,
+    /// or [None] for the successor if `key` was the most recently used one.
+    ///
+    /// Returns [None] if `key` is absent.
+    pub fn remove_returning_next<Q>(&mut self, key: &Q) -> Option<(K, V, Option<K>)>
+    where
+        K: Hash + Eq + Borrow<Q> + Clone,
+        Q: Hash + Eq + ?Sized,
+    {
+        let removed = self.kv_storage.take(KeyRef::new(key))?;
+        assert!(removed.ref_count.link.is_linked());
+        self.policy.on_remove(PolicyNode::new(&removed.ref_count));
+
+        let next = {
+            let queue = &mut self.recency_queue;
+            let mut cursor = unsafe { queue.cursor_mut_from_ptr(removed.ref_count.deref()) };
+            let next = cursor.peek_next().get().map(|node| node.key.clone());
+            cursor.remove();
+            next
+        };
+        invariant_eq!(removed.strong_ref_count(), 1);
+
+        let (key, val) = removed.into_pair();
+        Some((key, val, next))
+    }
+
+    /// Same as `resize`, but rejects `new_max_len` below `floor`, leaving the cache untouched.
+    pub fn try_resize_min(
+        &mut self,
+        new_max_len: NonZeroUsize,
+        floor: NonZeroUsize,
+    ) -> Result<Vec<(K, V)>, ResizeError>
+    where
+        K: Hash + Eq,
+    {
+        if new_max_len < floor {
+            return Err(ResizeError {
+                requested: new_max_len,
+                floor,
+            });
+        }
+        Ok(self.resize(new_max_len))
+    }
+
+    /// Sets `max_len()` to the current `len()`, so that any new key evicts.
+    ///
+    /// Returns evicted pairs like `resize`, which is always empty, as nothing exceeds the current length.
+    /// An empty cache is left as is, since `max_len()` can't be zero.
+    pub fn resize_to_len(&mut self) -> Vec<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        match NonZeroUsize::new(self.len()) {
+            Some(new_max_len) => self.resize(new_max_len),
+            None => Vec::new(),
+        }
+    }
+
+    /// Evicts up to `n` least recently used entries, sending each one over `tx`.
+    ///
+    /// If the receiver is gone, stops and puts the unsent entry back as the least recently used one,
+    /// leaving it and the rest resident. Returns the number of sent entries.
+    #[cfg(feature = "spill")]
+    pub fn spill_oldest(&mut self, n: usize, tx: &Sender<(K, V)>) -> usize
+    where
+        K: Hash + Eq,
+    {
+        let mut spilled = 0;
+        while spilled < n {
+            let Some(oldest) = self.pop_front_node() else {
+                break;
+            };
+            let insertion_seq = oldest.ref_count.insertion_seq;
+            let accesses = oldest.ref_count.accesses.get();
+            #[cfg(feature = "ttl")]
+            let written_at = oldest.ref_count.written_at.get();
+
+            if let Err(SendError((key, val))) = tx.send(oldest.into_pair()) {
+                let entry = RefNode::new(key, val, insertion_seq);
+                entry.ref_count.accesses.set(accesses);
+                #[cfg(feature = "ttl")]
+                entry.ref_count.written_at.set(written_at);
+                self.kv_storage.insert(entry.clone());
+                self.policy.on_insert(PolicyNode::new(&entry.ref_count));
+                self.recency_queue.push_front(entry.ref_count);
+                break;
+            }
+            spilled += 1;
+        }
+        spilled
+    }
+
+    /// Evicts the policy's victims until sizes of the remaining entries, computed by `size_of`,
+    /// sum up to at most `target_bytes`, see [Policy::choose_victim].
+    ///
+    /// Sizes are computed once per entry, at the time of the call. Returns evicted pairs
+    /// in the order they were chosen, unless a listener is registered with `on_evict`.
+    pub fn trim_to_bytes<F>(&mut self, target_bytes: usize, mut size_of: F) -> Vec<(K, V)>
+    where
+        K: Hash + Eq,
+        F: FnMut(&K, &V) -> usize,
+    {
+        // keyed by node addresses, which stay put until the nodes are dropped
+        let sizes: HashMap<*const Node<K, V>, usize> = self
+            .recency_queue
+            .iter()
+            .map(|node| (ptr::from_ref(node), size_of(&node.key, node.value())))
+            .collect();
+        let mut total: usize = sizes.values().sum();
+
+        let mut evicted = Vec::new();
+        while total > target_bytes {
+            let removed = self.evict_node();
+            invariant!(removed.is_some());
+            let removed = removed.unwrap();
+            let size = sizes.get(&Rc::as_ptr(&removed.ref_count));
+            invariant!(size.is_some());
+            total = total.saturating_sub(size.copied().unwrap_or(0));
+            evicted.extend(self.notify_evicted(removed, EvictionReason::Trim));
+        }
+        evicted
+    }
+
+    /// Moves all entries of `other` into the cache, merging values of colliding keys.
+    ///
+    /// Entries of `other` are taken from its least recently used to its most recently used one.
+    /// If the key is present, `combine(existing, incoming)` updates the value in place
+    /// and the key's considered the last used one, the entry keeps its insertion sequence number,
+    /// node id, expiration time and retrieval count, merging counts as one more retrieval.
+    /// Otherwise the pair is inserted as by `insert`, evicting if `max_len()` is reached. Entries rejected by the read validator or expired ones
+    /// count as absent and are replaced.
+    pub fn merge_with<F>(&mut self, other: LRUCache<K, V, S, P>, mut combine: F)
+    where
+        K: Hash + Eq,
+        F: FnMut(&mut V, V),
+    {
+        for (key, val) in other.into_pairs() {
+            let found = self.kv_storage.get(KeyRef::new(&key));
+            let Some(existing) = found.filter(|entry| self.is_valid(entry)) else {
+                self.insert(key, val);
+                continue;
+            };
+
+            // the cache is borrowed uniquely, nothing else refers to the value
+            combine(unsafe { existing.value_mut() }, val);
+            existing.count_access();
+            self.policy.on_access(PolicyNode::new(&existing.ref_count));
+            Self::move_to_back(&mut self.recency_queue, existing);
+        }
+    }
+
+    /// Performs `op` by calling the corresponding method.
+    ///
+    /// Lets a stream of operations drive the cache, e.g. to compare it against a reference model.
+    pub fn apply(&mut self, op: Op<K, V>) -> OpResult<K, V>
+    where
+        K: Hash + Eq,
+        V: Clone,
+    {
+        match op {
+            Op::Insert(key, val) => OpResult::Insert(self.insert(key, val)),
+            Op::Get(key) => OpResult::Get(self.get(&key).cloned()),
+            Op::Remove(key) => OpResult::Remove(self.remove(&key)),
+            Op::Resize(new_max_len) => OpResult::Resize(self.resize(new_max_len)),
+        }
+    }
+
+    /// Copies contents, recency order and `max_len()` of the cache.
+    /// Does not affect order of elements removal.
+    pub fn snapshot(&self) -> Snapshot<K, V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let entries = self
+            .recency_queue
+            .iter()
+            .map(|node| (node.key.clone(), node.value().clone()))
+            .collect();
+        Snapshot {
+            entries,
+            max_len: self.max_len(),
+        }
+    }
+
+    /// Copies up to `buf.len()` pairs into `buf`, from the most recently used to the least recently used.
+    ///
+    /// If `buf` is shorter than `len()`, only the most recently used pairs are copied,
+    /// if it's longer, elements after the copied ones are left untouched.
+    /// Returns the number of copied pairs. Does not affect order of elements removal.
+    pub fn copy_into_slice(&self, buf: &mut [(K, V)]) -> usize
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut copied = 0;
+        for (slot, node) in buf.iter_mut().zip(self.recency_queue.iter().rev()) {
+            *slot = (node.key.clone(), node.value().clone());
+            copied += 1;
+        }
+        copied
+    }
+
+    /// Replaces contents of the cache with the ones from `snapshot`.
+    ///
+    /// Entries are inserted in the order they're stored in the snapshot,
+    /// so a snapshot taken by `snapshot()` restores the same recency order.
+    /// Already allocated storage is reused.
+    pub fn restore(&mut self, snapshot: Snapshot<K, V>)
+    where
+        K: Hash + Eq,
+    {
+        while self.pop_front_node().is_some() {}
+
+        let Snapshot { entries, max_len } = snapshot;
+        self.max_len = NonZeroUsize::new(max_len);
+        self.kv_storage.reserve(entries.len().min(self.max_len()));
+        for (key, val) in entries {
+            self.insert(key, val);
+        }
+    }
+
+    /// Iterate over keys of the recently evicted entries, from the oldest eviction to the newest one.
+    ///
+    /// Empty unless the cache was created `with_eviction_history`. Evictions are the entries
+    /// the listener registered with `on_evict` takes, whether there's one or not, see [EvictionReason].
+    pub fn recent_evictions(&self) -> impl Iterator<Item = &K> {
+        self.eviction_history
+            .iter()
+            .flat_map(|history| history.keys.iter())
+    }
+
+    /// Returns numbers of retrievals by their reuse distances: the `d`-th element counts
+    /// `get`s of a key made after `d` other distinct keys were retrieved since its previous `get`.
+    ///
+    /// Hits and misses are counted alike, the very first retrieval of a key is not counted.
+    /// If most distances exceed `max_len()`, the cache is too small for the workload.
+    ///
+    /// Every distinct key ever retrieved is remembered and each `get` scans them,
+    /// so it's meant for profiling only.
+    #[cfg(feature = "profiling")]
+    pub fn reuse_distance_histogram(&self) -> Vec<u64> {
+        self.reuse_profile.histogram.clone()
+    }
+
+    /// Returns hits and misses of retrieving methods since creation or the last `reset_stats`.
+    ///
+    /// Only lookups that make a key the last used one are counted,
+    /// methods that do not affect order of elements removal leave the counters as is.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Zeroes the counters returned by `stats`.
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    /// Collects a health report of the cache.
+    ///
+    /// Walks the whole recency queue, so it's O(n).
+    /// Does not affect order of elements removal.
+    pub fn diagnostics(&self) -> CacheDiagnostics
+    where
+        K: Hash + Eq,
+    {
+        let len = self.len();
+        let max_len = self.max_len();
+
+        let mut queue_len = 0;
+        let mut all_stored = true;
+        for node in self.recency_queue.iter() {
+            queue_len += 1;
+            all_stored &= self
+                .kv_storage
+                .get(KeyRef::new(&node.key))
+                .is_some_and(|entry| ptr::eq(entry.ref_count.deref(), node));
+        }
+
+        CacheDiagnostics {
+            len,
+            max_len,
+            capacity: self.kv_storage.capacity(),
+            queue_len,
+            consistent: all_stored && queue_len == len && len <= max_len,
+        }
+    }
+
+    /// Reconciles the recency queue with the storage, should they ever diverge.
+    ///
+    /// Storage is authoritative for contents: queued nodes absent from storage are unlinked,
+    /// stored nodes missing from the queue are put to its least recently used end.
+    /// If more than `max_len()` entries are left, the policy's victims are evicted as by `evict_until`.
+    ///
+    /// Walks all the entries, so it's O(n) and meant for recovery, not routine use.
+    pub fn repair(&mut self)
+    where
+        K: Hash + Eq,
+    {
+        let queue = &mut self.recency_queue;
+        let mut cursor = queue.front_mut();
+        while let Some(node) = cursor.get() {
+            let is_stored = self
+                .kv_storage
+                .get(KeyRef::new(&node.key))
+                .is_some_and(|entry| ptr::eq(entry.ref_count.deref(), node));
+            if is_stored {
+                cursor.move_next();
+            } else {
+                cursor.remove();
+            }
+        }
+
+        for entry in self.kv_storage.iter() {
+            if !entry.ref_count.link.is_linked() {
+                queue.push_front(entry.ref_count.clone());
+            }
+        }
+
+        // `max_len()` asserts the very bound being restored
+        let max_len = self.max_len.map_or(0, NonZeroUsize::get);
+        self.evict_until(|cache| cache.len() <= max_len);
+    }
+
+    /// Consumes the cache, returning all pairs sorted by `cmp` regardless of recency.
+    pub fn into_sorted_by<F>(self, cmp: F) -> Vec<(K, V)>
+    where
+        K: Hash + Eq,
+        F: FnMut(&(K, V), &(K, V)) -> Ordering,
+    {
+        let mut pairs = self.into_pairs();
+        pairs.sort_by(cmp);
+        pairs
+    }
+
+    /// Consumes the cache, moving its pairs into a map, which drops the recency order.
+    pub fn into_hashmap(self) -> HashMap<K, V>
+    where
+        K: Hash + Eq,
+    {
+        let mut map = HashMap::with_capacity(self.len());
+        map.extend(self);
+        map
+    }
+
+    /// Rebuilds the storage to fit the current `len()`, if less than half of its capacity is in use.
+    ///
+    /// Worth calling after removing many entries from a cache, that won't be refilled soon:
+    /// it releases memory and shortens probing, at the cost of rehashing every entry.
+    /// Further insertions reallocate the storage back as needed.
+    /// Contents and recency order are not affected.
+    pub fn compact(&mut self)
+    where
+        K: Hash + Eq,
+    {
+        if self.len() * 2 < self.kv_storage.capacity() {
+            self.kv_storage.shrink_to(self.len());
+        }
+    }
+
+    /// Reserves storage for at least `additional` more entries, regardless of `max_len()`.
+    /// Doesn't change `max_len()`.
+    pub fn reserve(&mut self, additional: usize)
+    where
+        K: Hash + Eq,
+    {
+        self.kv_storage.reserve(additional);
+    }
+
+    /// Shrinks the storage as much as possible for the current `len()`, unlike `compact`
+    /// doing so unconditionally. Doesn't change `max_len()`.
+    pub fn shrink_to_fit(&mut self)
+    where
+        K: Hash + Eq,
+    {
+        self.kv_storage.shrink_to_fit();
+    }
+
+    /// Keeps only entries matching `f`, preserving their recency order.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        K: Hash + Eq,
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.retain_nodes(|node| f(&node.key, node.value()));
+    }
+
+    /// Removes every expired entry, see `with_ttl`.
+    #[cfg(feature = "ttl")]
+    pub fn purge_expired(&mut self)
+    where
+        K: Hash + Eq,
+    {
+        let Some(ttl) = self.ttl.take() else {
+            return;
+        };
+        let now = ttl.clock.now();
+        self.retain_nodes(|node| !ttl.is_expired(node.written_at.get(), now));
+        self.ttl = Some(ttl);
+    }
+
+    fn retain_nodes<F>(&mut self, mut f: F)
+    where
+        K: Hash + Eq,
+        F: FnMut(&Node<K, V>) -> bool,
+    {
+        let mut cursor = self.recency_queue.front_mut();
+        while let Some(node) = cursor.get() {
+            if f(node) {
+                cursor.move_next();
+                continue;
+            }
+            self.policy.on_remove(PolicyNode::new(node));
+            let removed = cursor.remove();
+            invariant!(removed.is_some());
+            let removed = removed.unwrap();
+            let stored = self.kv_storage.remove(KeyRef::new(&removed.key));
+            invariant!(stored);
+            invariant_eq!(Rc::strong_count(&removed), 1);
+        }
+    }
+
+    /// Removes every entry matching `f`, returning the number of removed entries.
+    ///
+    /// If more than half of the entries are removed, the storage is shrunk to fit the rest,
+    /// since a cache pruned that much is unlikely to be refilled soon.
+    /// Recency order of the remaining entries is preserved.
+    pub fn purge<F>(&mut self, mut f: F) -> usize
+    where
+        K: Hash + Eq,
+        F: FnMut(&K, &V) -> bool,
+    {
+        let len_before = self.len();
+        self.retain(|key, val| !f(key, val));
+
+        let removed = len_before - self.len();
+        if removed * 2 > len_before {
+            self.kv_storage.shrink_to_fit();
+        }
+        removed
+    }
+
+    /// Iterate over elements in an unspecified order.
+    /// Does not affect order of elements removal.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)>
+    where
+        K: Hash + Eq,
+    {
+        self.kv_storage
+            .iter()
+            .map(|elem| (elem.key(), elem.value()))
+    }
+
+    /// Iterate over keys in an unspecified order.
+    /// Does not affect order of elements removal.
+    pub fn keys(&self) -> impl Iterator<Item = &K>
+    where
+        K: Hash + Eq,
+    {
+        self.kv_storage.iter().map(RefNode::key)
+    }
+
+    /// Iterate over values in an unspecified order.
+    /// Does not affect order of elements removal.
+    pub fn values(&self) -> impl Iterator<Item = &V>
+    where
+        K: Hash + Eq,
+    {
+        self.kv_storage.iter().map(RefNode::value)
+    }
+
+    /// Iterate over mutable references to values in an unspecified order.
+    /// Does not affect order of elements removal.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V>
+    where
+        K: Hash + Eq,
+    {
+        // every node is stored once and yielded once, while the cache stays borrowed uniquely,
+        // so the references never alias
+        self.kv_storage
+            .iter()
+            .map(|entry| unsafe { entry.value_mut() })
+    }
+
+    /// Iterate over elements from the least recently used to the most recently used one.
+    /// Does not affect order of elements removal.
+    pub fn iter_lru(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> + ExactSizeIterator
+    where
+        K: Hash + Eq,
+    {
+        IterLru {
+            nodes: self.recency_queue.iter(),
+            len: self.len(),
+        }
+    }
+
+    /// Same as `iter_lru`, but from the most recently used element to the least recently used one.
+    pub fn iter_mru(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> + ExactSizeIterator
+    where
+        K: Hash + Eq,
+    {
+        self.iter_lru().rev()
+    }
+
+    /// Iterate over elements matching `f` in an unspecified order.
+    /// Does not affect order of elements removal.
+    pub fn iter_filter<'a, F>(&'a self, mut f: F) -> impl Iterator<Item = (&'a K, &'a V)> + 'a
+    where
+        K: Hash + Eq,
+        F: FnMut(&K, &V) -> bool + 'a,
+    {
+        self.iter().filter(move |(key, val)| f(key, val))
+    }
+
+    /// Iterate over keys with sizes of their entries computed by `size_of`, in an unspecified order.
+    /// Does not affect order of elements removal.
+    pub fn entry_sizes<F>(&self, mut size_of: F) -> impl Iterator<Item = (&K, usize)>
+    where
+        K: Hash + Eq,
+        F: FnMut(&K, &V) -> usize,
+    {
+        self.iter().map(move |(key, val)| (key, size_of(key, val)))
+    }
+
+    /// If key is present in storage, remove it from queue and storage and return removed node.
+    ///
+    /// If `len()` equals to `max_size()`, drop the first value from queue and storage and return [None].
+    ///
+    /// Just return [None] otherwise.
+    ///
+    /// Cache has a place to insert new entry.after call
+    fn drop_before_insertion(&mut self, key: &K) -> Option<RefNode<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        match self.make_room(key)? {
+            (replaced, DropReason::HasCollision) => Some(replaced),
+            (evicted, DropReason::FirstInQueue) => {
+                self.notify_evicted(evicted, EvictionReason::Capacity);
+                None
+            }
+        }
+    }
+
+    /// Same as `drop_before_insertion`, but returns the evicted node as well,
+    /// leaving the eviction listener to the caller
+    fn make_room(&mut self, key: &K) -> Option<(RefNode<K, V>, DropReason)>
+    where
+        K: Hash + Eq,
+    {
+        assert!(self.max_len.is_some(), "disabled cache can't store values");
+
+        let init_len = self.len();
+        let (to_remove, reason) = if self.kv_storage.contains(KeyRef::new(key)) {
+            (self.take_entry(key), DropReason::HasCollision)
+        } else if self.len() == self.max_len() {
+            // since [max_size] is not less than 1, there is at least one element in the queue,
+            //   thus, we remove something
+            (self.evict_node(), DropReason::FirstInQueue)
+        } else {
+            invariant!(self.len() < self.max_len());
+            return None;
+        };
+        invariant!(to_remove.is_some());
+        let to_remove = to_remove?;
+        invariant_eq!(to_remove.strong_ref_count(), 1);
+
+        invariant_eq!(init_len - 1, self.len());
+        invariant!(self.len() < self.max_len());
+        Some((to_remove, reason))
+    }
+
+    /// Records the eviction and passes the evicted pair to the listener, or returns it if there's none
+    fn notify_evicted(&mut self, evicted: RefNode<K, V>, reason: EvictionReason) -> Option<(K, V)> {
+        if let Some(history) = self.eviction_history.as_mut() {
+            history.record(evicted.key());
+        }
+
+        let (key, val) = evicted.into_pair();
+        match self.eviction_listener.as_mut() {
+            Some(listener) => {
+                (listener.callback)(key, val, reason);
+                None
+            }
+            None => Some((key, val)),
+        }
+    }
+
+    /// Unlinks the least recently used entry and removes it from storage.
+    ///
+    /// The returned node holds the only reference left.
+    fn pop_front_node(&mut self) -> Option<RefNode<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        self.pop_node(Victim::LeastRecent)
+    }
+
+    /// Same as `pop_front_node`, but the node is chosen by the policy.
+    /// It's up to the caller to pass it to `notify_evicted`
+    fn evict_node(&mut self) -> Option<RefNode<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        let victim = self
+            .policy
+            .choose_victim(Nodes::new(self.recency_queue.iter()));
+        let node: *const Node<K, V> =
+            Self::find_victim(&self.recency_queue, &self.kv_storage, victim)?;
+        // SAFETY: the node is stored, so it's alive until `take_entry` returns it
+        self.take_entry(unsafe { &(*node).key })
+    }
+
+    /// Unlinks the entry `victim` points at and removes it from storage.
+    ///
+    /// The returned node holds the only reference left.
+    fn pop_node(&mut self, victim: Victim<'_, K>) -> Option<RefNode<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        let node: *const Node<K, V> =
+            Self::find_victim(&self.recency_queue, &self.kv_storage, victim)?;
+        // SAFETY: the node is stored, so it's alive until `take_entry` returns it
+        let removed = self.take_entry(unsafe { &(*node).key });
+        invariant!(removed
+            .as_ref()
+            .is_some_and(|removed| removed.strong_ref_count() == 1));
+        removed
+    }
+
+    /// Unlinks the entry of `key` and removes it from storage.
+    ///
+    /// The returned node holds the only reference left.
+    fn take_entry<Q>(&mut self, key: &Q) -> Option<RefNode<K, V>>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let removed = self.kv_storage.take(KeyRef::new(key))?;
+        self.policy.on_remove(PolicyNode::new(&removed.ref_count));
+        Self::unlink(&mut self.recency_queue, &removed);
+        Some(removed)
+    }
+
+    /// Empties the cache, returning pairs from the least recently used to the most recently used.
+    fn into_pairs(mut self) -> Vec<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        let mut pairs = Vec::with_capacity(self.len());
+        while let Some(removed) = self.pop_front_node() {
+            pairs.push(removed.into_pair());
+        }
+        invariant!(self.kv_storage.is_empty());
+        pairs
+    }
+
+    /// Requires Cache to have free space for insertion
+    /// Reserves storage according to `growth_factor` if it's full
+    fn grow_storage(&mut self)
+    where
+        K: Hash + Eq,
+    {
+        let Some(factor) = self.growth_factor else {
+            return;
+        };
+        let capacity = self.kv_storage.capacity();
+        if self.len() < capacity {
+            return;
+        }
+
+        let grown = (capacity.max(1) as f64 * factor).ceil() as usize;
+        let additional = grown.min(self.max_len()) - self.len();
+        self.kv_storage.reserve(additional.max(1));
+    }
+
+    fn check_ref_count(entry: &RefNode<K, V>) -> Result<(), CacheError> {
+        match entry.strong_ref_count() {
+            2 => Ok(()),
+            count => Err(CacheError::UnexpectedRefCount(count)),
+        }
+    }
+
+    /// Requires Cache to be non-empty
+    /// Returns the stored entry of the least recently used key
+    fn front_entry(&self) -> &RefNode<K, V>
+    where
+        K: Hash + Eq,
+    {
+        let front = self.recency_queue.front().clone_pointer();
+        invariant!(front.is_some());
+
+        let entry = self.kv_storage.get(KeyRef::new(&front.unwrap().key));
+        invariant!(entry.is_some());
+        entry.unwrap()
+    }
+
+    /// Requires Cache to be non-empty
+    /// Returns the stored entry of the most recently used key
+    fn back_entry(&self) -> &RefNode<K, V>
+    where
+        K: Hash + Eq,
+    {
+        let back = self.recency_queue.back().clone_pointer();
+        invariant!(back.is_some());
+
+        let entry = self.kv_storage.get(KeyRef::new(&back.unwrap().key));
+        invariant!(entry.is_some());
+        entry.unwrap()
+    }
+
+    /// Looks `key` up in storage, making a valid entry the last used one
+    /// An entry failing validation is removed as evicted and counted as a miss
+    fn lookup<Q>(&mut self, key: &Q) -> Option<&RefNode<K, V>>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // a pointer lets an invalid entry be removed, while a valid one is returned borrowed
+        let found = self.kv_storage.get(KeyRef::new(key)).map(ptr::from_ref);
+        // SAFETY: the storage is not modified until the invalid entry is removed
+        let found = found.map(|entry| unsafe { &*entry });
+        let valid = found.filter(|entry| self.is_valid(entry));
+
+        match (found, valid) {
+            (_, Some(entry)) => {
+                entry.count_access();
+                if self.policy.on_access(PolicyNode::new(&entry.ref_count)) {
+                    Self::move_to_back(&mut self.recency_queue, entry);
+                }
+            }
+            (Some(_), None) => {
+                let removed = self.take_entry(key);
+                invariant!(removed.is_some());
+                if let Some(removed) = removed {
+                    self.notify_evicted(removed, EvictionReason::Invalid);
+                }
+            }
+            (None, None) => {}
+        }
+        match valid {
+            Some(_) => self.stats.hits += 1,
+            None => {
+                self.stats.misses += 1;
+                self.count_miss();
+            }
+        }
+        valid
+    }
+
+    fn is_valid(&self, entry: &RefNode<K, V>) -> bool {
+        #[cfg(feature = "ttl")]
+        if let Some(ttl) = self.ttl.as_ref() {
+            if ttl.is_expired(entry.ref_count.written_at.get(), ttl.clock.now()) {
+                return false;
+            }
+        }
+
+        self.read_validator
+            .as_ref()
+            .is_none_or(|validator| (validator.is_valid.borrow_mut())(entry.key(), entry.value()))
+    }
+
+    /// Increments the counter registered with `with_miss_counter`, if there's one
+    fn count_miss(&self) {
+        if let Some(counter) = self.miss_counter.as_ref() {
+            counter.set(counter.get() + 1);
+        }
+    }
+
+    fn check_pressure(&mut self) {
+        let (len, max_len) = (self.len(), self.max_len());
+        let Some(hook) = self.pressure_hook.as_mut() else {
+            return;
+        };
+
+        let is_above = len as f64 / max_len as f64 >= hook.threshold;
+        if is_above && !hook.fired {
+            (hook.callback)(len, max_len);
+        }
+        hook.fired = is_above;
+    }
+
+    /// Returns the node `victim` points at, [None] if the queue is empty
+    // see `with_storage_capacity` on the key type
+    #[allow(clippy::mutable_key_type)]
+    fn find_victim<'a>(
+        queue: &'a LinkedList<NodeAdapter<K, V>>,
+        storage: &'a HashSet<RefNode<K, V>, S>,
+        victim: Victim<'_, K>,
+    ) -> Option<&'a Node<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        match victim {
+            Victim::LeastRecent => queue.front().get(),
+            Victim::MostRecent => queue.back().get(),
+            Victim::Key(key) => match storage.get(KeyRef::new(key)) {
+                Some(entry) => Some(entry.ref_count.deref()),
+                None => queue.front().get(),
+            },
+        }
+    }
+
+    fn move_to_back(queue: &mut LinkedList<NodeAdapter<K, V>>, entry: &RefNode<K, V>) {
+        Self::unlink(queue, entry);
+        queue.push_back(entry.ref_count.clone());
+    }
+
+    fn unlink(queue: &mut LinkedList<NodeAdapter<K, V>>, entry: &RefNode<K, V>) {
+        assert!(entry.ref_count.link.is_linked());
+        invariant_eq!(entry.strong_ref_count(), 2);
+        {
+            let mut entry_cursor = unsafe { queue.cursor_mut_from_ptr(entry.ref_count.deref()) };
+            entry_cursor.remove();
+        }
+        invariant_eq!(entry.strong_ref_count(), 1);
+    }
+
+    /// Requires Cache to have free space for insertion
+    /// Puts new key-value pair, pushes `key` to the end of the queue
+    fn push_entry(&mut self, key: K, val: V)
+    where
+        K: Hash + Eq,
+    {
+        self.push_counted_entry(key, val, 0);
+    }
+
+    /// Same as `push_entry`, but the entry starts with `accesses` retrievals
+    fn push_counted_entry(&mut self, key: K, val: V, accesses: u64)
+    where
+        K: Hash + Eq,
+    {
+        invariant!(self.len() < self.max_len());
+
+        self.grow_storage();
+
+        let entry = RefNode::new(key, val, self.insertion_counter);
+        entry.ref_count.accesses.set(accesses);
+        self.insertion_counter += 1;
+        #[cfg(feature = "ttl")]
+        if let Some(ttl) = self.ttl.as_ref() {
+            entry.ref_count.written_at.set(Some(ttl.clock.now()));
+        }
+        invariant_eq!(entry.strong_ref_count(), 1);
+
+        self.kv_storage.insert(entry.clone());
+        self.recency_queue.push_back(entry.ref_count);
+        if let Some(node) = self.recency_queue.back().get() {
+            self.policy.on_insert(PolicyNode::new(node));
+        }
+
+        invariant!(self.len() <= self.max_len());
 
-        assert!(self.len() <= self.max_len());
+        if cfg!(all(debug_assertions, not(feature = "unchecked"))) {
+            self.assert_back_entry_stored();
+        }
+    }
 
-        let borrowed_queue = self.recency_queue.borrow();
-        let pushed_to_queue = borrowed_queue.back().get();
+    /// Checks that the most recently used node is the one stored for its key
+    fn assert_back_entry_stored(&self)
+    where
+        K: Hash + Eq,
+    {
+        let pushed_to_queue = self.recency_queue.back().get();
         assert!(pushed_to_queue.is_some());
 
-        let pushed_to_stg = self.kv_storage.get(&pushed_to_queue.unwrap().key);
+        let pushed_to_stg = self
+            .kv_storage
+            .get(KeyRef::new(&pushed_to_queue.unwrap().key));
         assert!(pushed_to_stg.is_some());
 
         assert_eq!(pushed_to_stg.unwrap().strong_ref_count(), 2);
@@ -312,3 +2560,254 @@ impl<K, V> LRUCache<K, V> {
         ));
     }
 }
+
+/// Owning iterator over pairs of a cache, from the least recently used to the most recently used one.
+#[derive(Debug)]
+pub struct IntoIter<K, V, S = RandomState, P = Lru> {
+    cache: LRUCache<K, V, S, P>,
+}
+
+impl<K, V, S, P> Iterator for IntoIter<K, V, S, P>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cache.pop_lru()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.cache.len(), Some(self.cache.len()))
+    }
+}
+
+impl<K, V, S, P> ExactSizeIterator for IntoIter<K, V, S, P>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+}
+
+impl<K, V, S, P> IntoIterator for LRUCache<K, V, S, P>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, S, P>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { cache: self }
+    }
+}
+
+/// Iterator over pairs of a cache in the recency order, see [LRUCache::iter_lru].
+struct IterLru<'a, K, V> {
+    nodes: linked_list::Iter<'a, NodeAdapter<K, V>>,
+    /// Number of nodes not yielded yet
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for IterLru<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.next()?;
+        self.len -= 1;
+        Some((&node.key, node.value()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterLru<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.next_back()?;
+        self.len -= 1;
+        Some((&node.key, node.value()))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterLru<'_, K, V> {}
+
+/// Draining iterator over pairs of a cache, see [LRUCache::drain].
+///
+/// Pairs not consumed are removed when the iterator is dropped.
+#[derive(Debug)]
+pub struct Drain<'a, K, V, S = RandomState, P = Lru>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+    cache: &'a mut LRUCache<K, V, S, P>,
+}
+
+impl<K, V, S, P> Iterator for Drain<'_, K, V, S, P>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cache.pop_lru()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.cache.len(), Some(self.cache.len()))
+    }
+}
+
+impl<K, V, S, P> ExactSizeIterator for Drain<'_, K, V, S, P>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, P: Policy<K, V>> Drop for Drain<'_, K, V, S, P> {
+    fn drop(&mut self) {
+        self.cache.clear();
+    }
+}
+
+/// Iterator over pairs evicted by [LRUCache::resize_drain].
+///
+/// Pairs not consumed are evicted when the iterator is dropped.
+#[derive(Debug)]
+pub struct ResizeDrain<'a, K, V, S = RandomState, P = Lru>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+    cache: &'a mut LRUCache<K, V, S, P>,
+    /// Number of entries above `max_len` still resident
+    excess: usize,
+}
+
+impl<K, V, S, P> Iterator for ResizeDrain<'_, K, V, S, P>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.excess > 0 {
+            self.excess -= 1;
+            let removed = self.cache.evict_node();
+            invariant!(removed.is_some());
+            let evicted = self
+                .cache
+                .notify_evicted(removed.unwrap(), EvictionReason::Resize);
+            if evicted.is_some() {
+                return evicted;
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.cache.eviction_listener {
+            Some(_) => (0, Some(0)),
+            None => (self.excess, Some(self.excess)),
+        }
+    }
+}
+
+impl<K, V, S, P> ExactSizeIterator for ResizeDrain<'_, K, V, S, P>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, P: Policy<K, V>> Drop for ResizeDrain<'_, K, V, S, P> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// Retrieves a value like `peek`, without affecting order of elements removal,
+/// see `get` to make the key the last used one.
+///
+/// # Panics
+///
+/// Panics if the key is not present in the cache.
+impl<K, V, S, P, Q> Index<&Q> for LRUCache<K, V, S, P>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &Self::Output {
+        self.peek(key).expect("key is not present in the cache")
+    }
+}
+
+/// Collects pairs into a cache sized for the unique keys among them, so none is evicted.
+///
+/// Later pairs are more recently used, a repeated key keeps the last value as with `insert`.
+impl<K, V, S, P> FromIterator<(K, V)> for LRUCache<K, V, S, P>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+    P: Policy<K, V> + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let pairs: Vec<_> = iter.into_iter().collect();
+        let unique_keys = pairs
+            .iter()
+            .map(|(key, _)| key)
+            .collect::<HashSet<_>>()
+            .len();
+
+        let max_len = NonZeroUsize::new(unique_keys.max(1));
+        invariant!(max_len.is_some());
+        let max_len = max_len.unwrap();
+
+        let mut cache =
+            Self::with_storage_capacity(Some(max_len), unique_keys, S::default(), P::default());
+        for (key, val) in pairs {
+            cache.insert(key, val);
+        }
+        cache
+    }
+}
+
+/// Moves pairs of `map` into a cache sized for all of them, so none is evicted.
+///
+/// The recency order follows the map's iteration order, which is unspecified.
+impl<K, V, S, P, H> From<HashMap<K, V, H>> for LRUCache<K, V, S, P>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+    P: Policy<K, V> + Default,
+{
+    fn from(map: HashMap<K, V, H>) -> Self {
+        let max_len = NonZeroUsize::new(map.len()).unwrap_or(NonZeroUsize::MIN);
+        let capacity = map.len();
+        let mut cache =
+            Self::with_storage_capacity(Some(max_len), capacity, S::default(), P::default());
+        for (key, val) in map {
+            cache.insert(key, val);
+        }
+        cache
+    }
+}
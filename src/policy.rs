@@ -0,0 +1,252 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Debug, Formatter},
+    hash::Hash,
+};
+
+use intrusive_collections::linked_list;
+
+use crate::{Node, NodeAdapter};
+
+/// Entry to evict, see [Policy::choose_victim].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Victim<'a, K> {
+    /// The least recently used entry, the one `peek_lru` returns
+    LeastRecent,
+    /// The most recently used entry
+    MostRecent,
+    /// The entry of the given key. The least recently used one is evicted if the key is absent.
+    Key(&'a K),
+}
+
+/// Entry of a cache as seen by a [Policy].
+#[derive(Debug)]
+pub struct PolicyNode<'a, K, V> {
+    node: &'a Node<K, V>,
+}
+
+impl<K, V> Clone for PolicyNode<'_, K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for PolicyNode<'_, K, V> {}
+
+impl<'a, K, V> PolicyNode<'a, K, V> {
+    pub(crate) fn new(node: &'a Node<K, V>) -> Self {
+        Self { node }
+    }
+
+    pub fn key(&self) -> &'a K {
+        &self.node.key
+    }
+
+    pub fn value(&self) -> &'a V {
+        self.node.value()
+    }
+
+    /// Retrievals of the key, including the ones of replaced entries.
+    pub fn accesses(&self) -> u64 {
+        self.node.accesses.get()
+    }
+
+    /// Insertion sequence number, see [crate::LRUCache::insertion_seq].
+    pub fn insertion_seq(&self) -> u64 {
+        self.node.insertion_seq
+    }
+}
+
+/// Entries of a cache from the least recently used to the most recently used one,
+/// see [Policy::choose_victim].
+#[derive(Clone)]
+pub struct Nodes<'a, K, V> {
+    nodes: linked_list::Iter<'a, NodeAdapter<K, V>>,
+}
+
+impl<'a, K, V> Nodes<'a, K, V> {
+    pub(crate) fn new(nodes: linked_list::Iter<'a, NodeAdapter<K, V>>) -> Self {
+        Self { nodes }
+    }
+}
+
+impl<K, V> Debug for Nodes<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Nodes").finish_non_exhaustive()
+    }
+}
+
+impl<'a, K, V> Iterator for Nodes<'a, K, V> {
+    type Item = PolicyNode<'a, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(PolicyNode::new)
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Nodes<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.nodes.next_back().map(PolicyNode::new)
+    }
+}
+
+/// Eviction policy of a cache, see [crate::LRUCache::with_policy].
+///
+/// Entries are kept in the recency queue, a policy decides whether retrieving an entry
+/// moves it to the most recently used end and which entry the cache evicts when it's full.
+/// Hooks are called after the cache is updated, so they see the entry as it's stored.
+pub trait Policy<K, V> {
+    /// Called after a new entry is inserted as the most recently used one,
+    /// including the ones replacing an entry of the same key.
+    fn on_insert(&mut self, _node: PolicyNode<'_, K, V>) {}
+
+    /// Called on every successful retrieval, after it's counted in [PolicyNode::accesses].
+    /// Returns whether the entry becomes the most recently used one.
+    fn on_access(&mut self, node: PolicyNode<'_, K, V>) -> bool;
+
+    /// Called when an entry leaves the cache for any reason: evicted, replaced or removed explicitly.
+    fn on_remove(&mut self, _node: PolicyNode<'_, K, V>) {}
+
+    /// Called on every eviction, either due to lack of space or shrinking the cache.
+    ///
+    /// `nodes` walks the entries lazily, policies keeping track of entries on their own
+    /// needn't touch it.
+    fn choose_victim<'a>(&'a self, nodes: Nodes<'a, K, V>) -> Victim<'a, K>;
+
+    /// Orders entries the policy ranks equally, see [LRUCacheBuilder::tie_break].
+    ///
+    /// Called before the policy sees any entry. Policies ranking entries by recency alone
+    /// have no ties and ignore it.
+    ///
+    /// [LRUCacheBuilder::tie_break]: crate::LRUCacheBuilder::tie_break
+    fn set_tie_break(&mut self, _tie_break: TieBreak) {}
+}
+
+/// Which of equally ranked entries is evicted first, see [Policy::set_tie_break].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// The one retrieved or inserted the longest ago
+    #[default]
+    Recency,
+    /// The earliest inserted one, regardless of retrievals
+    Fifo,
+    /// The latest inserted one
+    Lifo,
+}
+
+/// Evicts the least recently used entry, the default policy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lru;
+
+impl<K, V> Policy<K, V> for Lru {
+    fn on_access(&mut self, _node: PolicyNode<'_, K, V>) -> bool {
+        true
+    }
+
+    fn choose_victim<'a>(&'a self, _nodes: Nodes<'a, K, V>) -> Victim<'a, K> {
+        Victim::LeastRecent
+    }
+}
+
+/// Evicts the earliest inserted entry, retrievals don't reorder entries.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fifo;
+
+impl<K, V> Policy<K, V> for Fifo {
+    fn on_access(&mut self, _node: PolicyNode<'_, K, V>) -> bool {
+        false
+    }
+
+    fn choose_victim<'a>(&'a self, _nodes: Nodes<'a, K, V>) -> Victim<'a, K> {
+        Victim::LeastRecent
+    }
+}
+
+/// Evicts the most recently used entry, for cyclic scans larger than the cache.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mru;
+
+impl<K, V> Policy<K, V> for Mru {
+    fn on_access(&mut self, _node: PolicyNode<'_, K, V>) -> bool {
+        true
+    }
+
+    fn choose_victim<'a>(&'a self, _nodes: Nodes<'a, K, V>) -> Victim<'a, K> {
+        Victim::MostRecent
+    }
+}
+
+/// Evicts the least retrieved entry, keeping the hot keys that were not used lately.
+///
+/// Retrievals are counted per key, replacing a value with `insert` counts as one too.
+/// Among equally retrieved entries the one retrieved or inserted the longest ago goes first,
+/// unless another [TieBreak] is set.
+/// Keys are indexed by their counts, so evictions take O(log n), at the cost of
+/// two clones of every stored key.
+#[derive(Debug, Clone)]
+pub struct Lfu<K> {
+    /// Keys by their ranks, the victim first
+    keys: BTreeMap<Rank, K>,
+    ranks: HashMap<K, Rank>,
+    /// Incremented on every insertion and retrieval
+    tick: u64,
+    tie_break: TieBreak,
+}
+
+/// Retrievals of a key, then its position among the equally retrieved ones
+type Rank = (u64, u64);
+
+impl<K> Default for Lfu<K> {
+    fn default() -> Self {
+        Self {
+            keys: BTreeMap::new(),
+            ranks: HashMap::new(),
+            tick: 0,
+            tie_break: TieBreak::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Lfu<K> {
+    fn rank<V>(&mut self, node: PolicyNode<'_, K, V>) {
+        self.tick += 1;
+        let tie = match self.tie_break {
+            TieBreak::Recency => self.tick,
+            TieBreak::Fifo => node.insertion_seq(),
+            TieBreak::Lifo => u64::MAX - node.insertion_seq(),
+        };
+        let rank = (node.accesses(), tie);
+        if let Some(previous) = self.ranks.insert(node.key().clone(), rank) {
+            self.keys.remove(&previous);
+        }
+        self.keys.insert(rank, node.key().clone());
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Policy<K, V> for Lfu<K> {
+    fn on_insert(&mut self, node: PolicyNode<'_, K, V>) {
+        self.rank(node);
+    }
+
+    fn on_access(&mut self, node: PolicyNode<'_, K, V>) -> bool {
+        self.rank(node);
+        true
+    }
+
+    fn on_remove(&mut self, node: PolicyNode<'_, K, V>) {
+        if let Some(rank) = self.ranks.remove(node.key()) {
+            self.keys.remove(&rank);
+        }
+    }
+
+    fn choose_victim<'a>(&'a self, _nodes: Nodes<'a, K, V>) -> Victim<'a, K> {
+        match self.keys.first_key_value() {
+            Some((_, key)) => Victim::Key(key),
+            None => Victim::LeastRecent,
+        }
+    }
+
+    fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = tie_break;
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Debug,
+    hash::{BuildHasher, Hash},
+    ops::{Deref, DerefMut},
+};
+
+use crate::{LRUCache, Lru, Policy};
+
+/// Guard of an entry inserted by [LRUCache::scoped_insert].
+///
+/// Removes the entry from the cache when dropped, unless [EvictOnDrop::keep] is called.
+/// Dereferences to the cache, so it can be used while the guard is alive.
+pub struct EvictOnDrop<'a, K, V, S = RandomState, P = Lru>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+    cache: &'a mut LRUCache<K, V, S, P>,
+    key: K,
+    /// Tells the guarded entry from a later one with the same key
+    insertion_seq: u64,
+    keep: bool,
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, P: Policy<K, V>> EvictOnDrop<'a, K, V, S, P> {
+    pub(crate) fn new(cache: &'a mut LRUCache<K, V, S, P>, key: K, insertion_seq: u64) -> Self {
+        Self {
+            cache,
+            key,
+            insertion_seq,
+            keep: false,
+        }
+    }
+
+    /// Returns the key of the guarded entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Keeps the entry in the cache after the guard is gone.
+    pub fn keep(mut self) {
+        self.keep = true;
+    }
+
+    fn is_guarded_resident(&self) -> bool {
+        self.cache.insertion_seq(&self.key) == Some(self.insertion_seq)
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, P: Policy<K, V>> Deref for EvictOnDrop<'_, K, V, S, P> {
+    type Target = LRUCache<K, V, S, P>;
+
+    fn deref(&self) -> &Self::Target {
+        self.cache
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, P: Policy<K, V>> DerefMut for EvictOnDrop<'_, K, V, S, P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.cache
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, P: Policy<K, V>> Drop for EvictOnDrop<'_, K, V, S, P> {
+    fn drop(&mut self) {
+        if !self.keep && self.is_guarded_resident() {
+            let removed = self.cache.take_entry(&self.key);
+            invariant!(removed.is_some());
+        }
+    }
+}
+
+impl<K, V, S, P> Debug for EvictOnDrop<'_, K, V, S, P>
+where
+    K: Hash + Eq + Debug,
+    S: BuildHasher,
+    P: Policy<K, V>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EvictOnDrop")
+            .field("key", &self.key)
+            .field("insertion_seq", &self.insertion_seq)
+            .field("keep", &self.keep)
+            .finish_non_exhaustive()
+    }
+}
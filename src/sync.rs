@@ -0,0 +1,95 @@
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    fmt::Debug,
+    hash::{BuildHasher, Hash},
+    num::NonZeroUsize,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::LRUCache;
+
+/// [LRUCache] shared between threads.
+///
+/// Every operation locks a single [Mutex] around the whole cache, so operations never run
+/// concurrently, lookups included: `get` relinks the entry, so even reads mutate the cache.
+/// Values are cloned out of the lock instead of being borrowed.
+///
+/// Operations panic if another thread panicked while holding the lock,
+/// since the cache may have been left inconsistent.
+pub struct SyncLRUCache<K, V, S = RandomState> {
+    cache: Mutex<SendCache<K, V, S>>,
+}
+
+/// Cache built by [SyncLRUCache] itself.
+///
+/// Its `Rc`s are shared only between the storage and the recency queue, never handed out,
+/// and it carries no miss counter, callbacks or custom policy,
+/// so moving it with its keys and values is sound.
+struct SendCache<K, V, S>(LRUCache<K, V, S>);
+
+unsafe impl<K: Send, V: Send, S: Send> Send for SendCache<K, V, S> {}
+
+impl<K, V> SyncLRUCache<K, V> {
+    /// Create cache with maximum of `max_len` elements, see [LRUCache::with_max_len].
+    pub fn with_max_len(max_len: NonZeroUsize) -> Self {
+        Self {
+            cache: Mutex::new(SendCache(LRUCache::with_max_len(max_len))),
+        }
+    }
+}
+
+impl<K, V, S: BuildHasher> SyncLRUCache<K, V, S> {
+    /// Create cache with maximum of `max_len` elements, hashing keys with `hasher`,
+    /// see [LRUCache::with_hasher].
+    pub fn with_hasher(max_len: NonZeroUsize, hasher: S) -> Self {
+        Self {
+            cache: Mutex::new(SendCache(LRUCache::with_hasher(max_len, hasher))),
+        }
+    }
+
+    /// Same as [LRUCache::insert].
+    pub fn insert(&self, key: K, val: V) -> Option<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        self.lock().0.insert(key, val)
+    }
+
+    /// Same as [LRUCache::get], but returns a copy of the value.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.lock().0.get(key).cloned()
+    }
+
+    /// Same as [LRUCache::remove].
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock().0.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SendCache<K, V, S>> {
+        self.cache.lock().expect("cache lock is poisoned")
+    }
+}
+
+impl<K, V, S> Debug for SyncLRUCache<K, V, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncLRUCache").finish_non_exhaustive()
+    }
+}
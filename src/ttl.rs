@@ -0,0 +1,40 @@
+use std::{
+    fmt::{Debug, Formatter},
+    time::{Duration, Instant},
+};
+
+/// Source of time for expiring entries, see [crate::LRUCache::with_ttl].
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// [Clock] reading [Instant::now].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+pub(crate) struct Ttl {
+    pub(crate) time_to_live: Duration,
+    pub(crate) clock: Box<dyn Clock>,
+}
+
+impl Ttl {
+    /// Whether an entry written at `written_at` is expired at `now`
+    pub(crate) fn is_expired(&self, written_at: Option<Instant>, now: Instant) -> bool {
+        written_at
+            .is_some_and(|written_at| now.saturating_duration_since(written_at) > self.time_to_live)
+    }
+}
+
+impl Debug for Ttl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ttl")
+            .field("time_to_live", &self.time_to_live)
+            .finish_non_exhaustive()
+    }
+}
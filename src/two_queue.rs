@@ -0,0 +1,234 @@
+use std::{
+    borrow::Borrow,
+    collections::{hash_map::RandomState, HashSet},
+    hash::{BuildHasher, Hash},
+    num::NonZeroUsize,
+    ops::Deref,
+};
+
+use intrusive_collections::LinkedList;
+
+use crate::{KeyRef, NodeAdapter, RefNode};
+
+/// Segmented LRU cache, resisting scans that would flush the hot entries out of [crate::LRUCache].
+///
+/// New keys land in the probationary segment, retrieving a key there promotes it to
+/// the protected one. Entries are evicted from the probationary segment first, so keys
+/// retrieved only once, e.g. by a scan, never push out the ones retrieved repeatedly.
+/// When the protected segment overflows, its least recently used entry is demoted
+/// to the most recently used position of the probationary one.
+#[derive(Debug)]
+pub struct TwoQueueCache<K, V, S = RandomState> {
+    kv_storage: HashSet<RefNode<K, V>, S>,
+    /// Entries retrieved at most once since insertion, evicted first
+    probation: LinkedList<NodeAdapter<K, V>>,
+    /// Entries retrieved more than once
+    protected: LinkedList<NodeAdapter<K, V>>,
+    /// Length of `protected`, which the list doesn't track
+    protected_count: usize,
+    probation_len: NonZeroUsize,
+    protected_len: NonZeroUsize,
+}
+
+impl<K, V> TwoQueueCache<K, V> {
+    /// Create cache with maximum of `probation_len + protected_len` elements,
+    /// up to `protected_len` of them retrieved repeatedly.
+    ///
+    /// Allocates capacity beforehand.
+    pub fn with_segments(probation_len: NonZeroUsize, protected_len: NonZeroUsize) -> Self {
+        Self::with_segments_and_hasher(probation_len, protected_len, RandomState::new())
+    }
+}
+
+impl<K, V, S: BuildHasher> TwoQueueCache<K, V, S> {
+    /// Same as `with_segments`, hashing keys with `hasher`.
+    pub fn with_segments_and_hasher(
+        probation_len: NonZeroUsize,
+        protected_len: NonZeroUsize,
+        hasher: S,
+    ) -> Self {
+        let max_len = probation_len.saturating_add(protected_len.get());
+        Self {
+            kv_storage: HashSet::with_capacity_and_hasher(max_len.get(), hasher),
+            probation: LinkedList::new(NodeAdapter::new()),
+            protected: LinkedList::new(NodeAdapter::new()),
+            protected_count: 0,
+            probation_len,
+            protected_len,
+        }
+    }
+
+    /// Adds an element to the probationary segment.
+    ///
+    /// If the `key` is new, returns [None], evicting the least recently used probationary entry
+    /// if the cache is full, or the protected one if there are none.
+    ///
+    /// If the `key` was present, returns previous key-value pair,
+    /// the replacement counts as a retrieval, see `get`.
+    pub fn insert(&mut self, key: K, val: V) -> Option<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        let replaced = self.take_entry(&key);
+        if replaced.is_none() && self.len() == self.max_len() {
+            let evicted = self.pop_lru();
+            invariant!(evicted.is_some());
+        }
+
+        let entry = RefNode::new(key, val, 0);
+        self.kv_storage.insert(entry.clone());
+        self.probation.push_back(entry.ref_count.clone());
+        invariant!(self.len() <= self.max_len());
+
+        if replaced.is_some() {
+            // lands at the back of the protected segment, whichever one the replaced entry was in
+            self.promote(&entry);
+        }
+        replaced.map(RefNode::into_pair)
+    }
+
+    /// Retrieves a value associated with `key`.
+    ///
+    /// A probationary key is promoted to the protected segment,
+    /// a protected one becomes the most recently used there.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.kv_storage.get(KeyRef::new(key))?.clone();
+        self.promote(&entry);
+        drop(entry);
+        self.peek(key)
+    }
+
+    /// Retrieves a value associated with `key`, if there is one.
+    /// Does not affect order of elements removal.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.kv_storage
+            .get(KeyRef::new(key))
+            .map(|entry| entry.value())
+    }
+
+    /// Removes `key` from the cache, returning its value if it was present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (_, val) = self.take_entry(key)?.into_pair();
+        Some(val)
+    }
+
+    /// Changes sizes of the segments, demoting protected entries that don't fit anymore
+    /// and evicting the least recently used ones that don't fit in the cache.
+    ///
+    /// Returns evicted pairs, probationary ones first.
+    pub fn resize(
+        &mut self,
+        probation_len: NonZeroUsize,
+        protected_len: NonZeroUsize,
+    ) -> Vec<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        self.probation_len = probation_len;
+        self.protected_len = protected_len;
+        while self.protected_count > protected_len.get() {
+            self.demote_protected_front();
+        }
+
+        let mut evicted = Vec::new();
+        while self.len() > self.max_len() {
+            let removed = self.pop_lru();
+            invariant!(removed.is_some());
+            evicted.extend(removed);
+        }
+        evicted
+    }
+
+    /// Sum of both segment sizes.
+    pub fn max_len(&self) -> usize {
+        self.probation_len
+            .saturating_add(self.protected_len.get())
+            .get()
+    }
+
+    pub fn len(&self) -> usize {
+        self.kv_storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kv_storage.is_empty()
+    }
+
+    /// Moves `entry` to the back of the protected segment, demoting an overflowing one
+    fn promote(&mut self, entry: &RefNode<K, V>) {
+        self.unlink(entry);
+        entry.ref_count.protected.set(true);
+        self.protected.push_back(entry.ref_count.clone());
+        self.protected_count += 1;
+
+        if self.protected_count > self.protected_len.get() {
+            self.demote_protected_front();
+        }
+    }
+
+    fn demote_protected_front(&mut self) {
+        let demoted = self.protected.pop_front();
+        invariant!(demoted.is_some());
+        let demoted = demoted.unwrap();
+        self.protected_count -= 1;
+        demoted.protected.set(false);
+        self.probation.push_back(demoted);
+    }
+
+    /// Removes the least recently used probationary pair, or the protected one if there are none
+    fn pop_lru(&mut self) -> Option<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        let node = match self.probation.pop_front() {
+            Some(node) => node,
+            None => {
+                let node = self.protected.pop_front()?;
+                self.protected_count -= 1;
+                node
+            }
+        };
+        let removed = RefNode { ref_count: node };
+        let was_removed = self.kv_storage.remove(KeyRef::new(removed.key()));
+        invariant!(was_removed);
+        Some(removed.into_pair())
+    }
+
+    /// Unlinks the entry of `key` from its segment and removes it from storage
+    fn take_entry<Q>(&mut self, key: &Q) -> Option<RefNode<K, V>>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let removed = self.kv_storage.take(KeyRef::new(key))?;
+        self.unlink(&removed);
+        invariant_eq!(removed.strong_ref_count(), 1);
+        Some(removed)
+    }
+
+    /// Unlinks `entry` from the segment it's in
+    fn unlink(&mut self, entry: &RefNode<K, V>) {
+        let node = entry.ref_count.deref();
+        let segment = match node.protected.get() {
+            true => {
+                self.protected_count -= 1;
+                &mut self.protected
+            }
+            false => &mut self.probation,
+        };
+        let unlinked = unsafe { segment.cursor_mut_from_ptr(node) }.remove();
+        invariant!(unlinked.is_some());
+    }
+}
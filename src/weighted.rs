@@ -0,0 +1,155 @@
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    fmt::Debug,
+    hash::{BuildHasher, Hash},
+    num::NonZeroUsize,
+};
+
+use crate::{LRUCache, Lru};
+
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> usize>;
+
+/// [LRUCache] bounded by the total weight of its entries instead of their number.
+///
+/// Weights are computed by the weigher given on creation, once on insertion and once on removal,
+/// so values are not available mutably.
+pub struct WeightedLRUCache<K, V, S = RandomState> {
+    cache: LRUCache<K, V, S>,
+    weigher: Weigher<K, V>,
+    weight: usize,
+    max_weight: usize,
+}
+
+impl<K, V> WeightedLRUCache<K, V> {
+    /// Create cache holding entries up to `max_weight` in total, each one weighing `weigher(key, val)`.
+    pub fn with_max_weight<F>(max_weight: usize, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> usize + 'static,
+    {
+        Self::with_max_weight_and_hasher(max_weight, weigher, RandomState::new())
+    }
+}
+
+impl<K, V, S: BuildHasher> WeightedLRUCache<K, V, S> {
+    /// Same as `with_max_weight`, hashing keys with `hasher`.
+    pub fn with_max_weight_and_hasher<F>(max_weight: usize, weigher: F, hasher: S) -> Self
+    where
+        F: Fn(&K, &V) -> usize + 'static,
+    {
+        // bounded by weight alone, the storage grows as needed
+        let cache = LRUCache::with_storage_capacity(Some(NonZeroUsize::MAX), 0, hasher, Lru);
+        Self {
+            cache,
+            weigher: Box::new(weigher),
+            weight: 0,
+            max_weight,
+        }
+    }
+
+    /// Adds an element to the cache, evicting the least recently used entries
+    /// until the total weight fits `max_weight()`.
+    ///
+    /// If the `key` was present, returns previous key-value pair as [LRUCache::insert] does.
+    /// A pair weighing more than `max_weight()` alone is not inserted, but returned back,
+    /// leaving the cache untouched.
+    pub fn insert(&mut self, key: K, val: V) -> Option<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        let weight = (self.weigher)(&key, &val);
+        if weight > self.max_weight {
+            return Some((key, val));
+        }
+
+        let replaced = self.cache.insert(key, val);
+        if let Some((key, val)) = replaced.as_ref() {
+            self.weight -= (self.weigher)(key, val);
+        }
+        self.weight += weight;
+        self.evict_overweight();
+        replaced
+    }
+
+    /// Same as [LRUCache::get].
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.get(key)
+    }
+
+    /// Same as [LRUCache::peek].
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.peek(key)
+    }
+
+    /// Removes `key` from the cache, returning its value if it was present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, val) = self.cache.take_entry(key)?.into_pair();
+        self.weight -= (self.weigher)(&key, &val);
+        Some(val)
+    }
+
+    /// Changes `max_weight()`, evicting the least recently used entries that don't fit anymore.
+    ///
+    /// Returns evicted pairs.
+    pub fn resize(&mut self, max_weight: usize) -> Vec<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        self.max_weight = max_weight;
+        self.evict_overweight()
+    }
+
+    /// Total weight of the entries.
+    pub fn current_weight(&self) -> usize {
+        self.weight
+    }
+
+    pub fn max_weight(&self) -> usize {
+        self.max_weight
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    fn evict_overweight(&mut self) -> Vec<(K, V)>
+    where
+        K: Hash + Eq,
+    {
+        let mut evicted = Vec::new();
+        while self.weight > self.max_weight {
+            let removed = self.cache.pop_lru();
+            invariant!(removed.is_some());
+            let (key, val) = removed.unwrap();
+            self.weight -= (self.weigher)(&key, &val);
+            evicted.push((key, val));
+        }
+        evicted
+    }
+}
+
+impl<K: Debug, V: Debug, S: Debug> Debug for WeightedLRUCache<K, V, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WeightedLRUCache")
+            .field("cache", &self.cache)
+            .field("weight", &self.weight)
+            .field("max_weight", &self.max_weight)
+            .finish_non_exhaustive()
+    }
+}
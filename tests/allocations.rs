@@ -0,0 +1,56 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    num::NonZeroUsize,
+};
+
+use lru_cache::LRUCache;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn identical_insert_dedup_does_not_allocate() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+
+    let before = allocations();
+    for _ in 0..100 {
+        assert_eq!(cache.insert_dedup(1, 10), Some((1, 10)));
+    }
+    assert_eq!(allocations(), before);
+
+    // a different value goes through the regular insertion
+    assert_eq!(cache.insert_dedup(1, 11), Some((1, 10)));
+    assert!(allocations() > before);
+
+    // the deduplicated key was still promoted
+    cache.insert_dedup(2, 20);
+    cache.insert(3, 30);
+    cache.insert(4, 40);
+    cache.insert(5, 50);
+    assert_eq!(cache.get(&1), None);
+    assert_eq!(cache.get(&2), Some(&20));
+}
@@ -1,10 +1,20 @@
-use std::num::NonZeroU32;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{hash_map::RandomState, BTreeSet},
+    hash::{BuildHasherDefault, Hasher},
+    num::NonZeroUsize,
+    rc::Rc,
+};
 
-use lru_cache::LRUCache;
+use lru_cache::{
+    CacheError, CacheStats, CapacityError, Entry, EvictionReason, Fifo, InsertOutcome, LRUCache,
+    Lfu, Mru, Nodes, Policy, PolicyNode, ResizeError, SyncLRUCache, TieBreak, TwoQueueCache,
+    Victim, WeightedLRUCache,
+};
 
 #[test]
 fn does_not_exceed_max_size() {
-    let mut cache = LRUCache::with_max_len(NonZeroU32::new(2).unwrap());
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
     assert_eq!(cache.len(), 0);
     assert_eq!(cache.max_len(), 2);
 
@@ -36,7 +46,7 @@ fn does_not_exceed_max_size() {
 
 #[test]
 fn insert_get() {
-    let mut cache = LRUCache::with_max_len(NonZeroU32::new(10).unwrap());
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(10).unwrap());
     assert_eq!(cache.len(), 0);
     assert_eq!(cache.max_len(), 10);
 
@@ -57,7 +67,7 @@ fn insert_get() {
 
 #[test]
 fn renewal_by_get() {
-    let mut cache = LRUCache::with_max_len(NonZeroU32::new(3).unwrap());
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
 
     cache.insert("one", 1);
     cache.insert("two", 2);
@@ -80,7 +90,7 @@ fn renewal_by_get() {
 
 #[test]
 fn resize_to_bigger() {
-    let mut cache = LRUCache::with_max_len(NonZeroU32::new(3).unwrap());
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
 
     cache.insert("1 + 1", 2);
     cache.insert("2 * 3", 6);
@@ -91,7 +101,7 @@ fn resize_to_bigger() {
     assert_eq!(cache.get(&"1 + 2"), Some(&3));
     assert_eq!(cache.get(&"1 + 1"), None);
 
-    cache.resize(NonZeroU32::new(4).unwrap());
+    cache.resize(NonZeroUsize::new(4).unwrap());
     assert_eq!(cache.max_len(), 4);
     assert_eq!(cache.len(), 3);
 
@@ -110,7 +120,7 @@ fn resize_to_bigger() {
 
 #[test]
 fn resize_to_smaller_without_shrink() {
-    let mut cache = LRUCache::with_max_len(NonZeroU32::new(3).unwrap());
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
 
     cache.insert(1, 2);
     cache.insert(2, 3);
@@ -118,7 +128,7 @@ fn resize_to_smaller_without_shrink() {
     assert_eq!(cache.len(), 2);
     assert_eq!(cache.max_len(), 3);
 
-    cache.resize(NonZeroU32::new(2).unwrap());
+    cache.resize(NonZeroUsize::new(2).unwrap());
 
     assert_eq!(cache.len(), 2);
     assert_eq!(cache.max_len(), 2);
@@ -129,7 +139,7 @@ fn resize_to_smaller_without_shrink() {
 
 #[test]
 fn resize_and_shrink() {
-    let mut cache = LRUCache::with_max_len(NonZeroU32::new(3).unwrap());
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
 
     cache.insert(1, 2);
     cache.insert(2, 3);
@@ -138,7 +148,7 @@ fn resize_and_shrink() {
     assert_eq!(cache.len(), 3);
     assert_eq!(cache.max_len(), 3);
 
-    cache.resize(NonZeroU32::new(1).unwrap());
+    cache.resize(NonZeroUsize::new(1).unwrap());
 
     assert_eq!(cache.max_len(), 1);
     assert_eq!(cache.len(), 1);
@@ -147,3 +157,1981 @@ fn resize_and_shrink() {
     assert_eq!(cache.get(&2), None);
     assert_eq!(cache.get(&1), None);
 }
+
+#[test]
+fn insertion_seq_ignores_recency() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    assert_eq!(cache.insertion_seq(&"c"), None);
+
+    let a_seq = cache.insertion_seq(&"a").unwrap();
+    let b_seq = cache.insertion_seq(&"b").unwrap();
+    assert!(a_seq < b_seq);
+
+    // getting "a" makes it most recent, but doesn't change the insertion order
+    cache.get(&"a");
+    assert_eq!(cache.insertion_seq(&"a"), Some(a_seq));
+
+    // re-insertion stamps the key anew
+    cache.insert("a", 3);
+    assert!(cache.insertion_seq(&"a").unwrap() > b_seq);
+}
+
+#[test]
+fn node_id_is_stable_while_resident() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+    assert_eq!(cache.node_id(&3), None);
+
+    let one_id = cache.node_id(&1).unwrap();
+    assert_ne!(Some(one_id), cache.node_id(&2));
+
+    // reordering doesn't move the node
+    cache.get(&1);
+    cache.insert(3, "three");
+    assert_eq!(cache.node_id(&1), Some(one_id));
+
+    // 2 was evicted
+    assert_eq!(cache.node_id(&2), None);
+}
+
+#[test]
+fn merge_with_combines_collisions() {
+    let mut counters = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    counters.insert("a", 1);
+    counters.insert("b", 2);
+    counters.insert("c", 3);
+
+    let mut shard = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    shard.insert("d", 10);
+    shard.insert("b", 20);
+
+    let (node_id, insertion_seq) = (counters.node_id(&"b"), counters.insertion_seq(&"b"));
+    counters.merge_with(shard, |existing, incoming| *existing += incoming);
+
+    assert_eq!(counters.len(), 3);
+    // combined in place
+    assert_eq!(counters.node_id(&"b"), node_id);
+    assert_eq!(counters.insertion_seq(&"b"), insertion_seq);
+    // "d" evicted "a", "b" was summed and became the most recent one
+    assert_eq!(counters.get(&"a"), None);
+    assert_eq!(counters.get(&"b"), Some(&22));
+    assert_eq!(counters.get(&"d"), Some(&10));
+    assert_eq!(counters.get(&"c"), Some(&3));
+}
+
+#[test]
+fn lazy_cache_behaves_like_regular() {
+    let mut cache = LRUCache::with_max_len_lazy(NonZeroUsize::new(2).unwrap());
+    assert_eq!(cache.max_len(), 2);
+
+    assert_eq!(cache.insert(1, 2), None);
+    assert_eq!(cache.insert(2, 3), None);
+    assert_eq!(cache.insert(3, 4), None);
+
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&1), None);
+    assert_eq!(cache.get(&2), Some(&3));
+    assert_eq!(cache.get(&3), Some(&4));
+}
+
+#[test]
+fn lazy_cache_allocates_on_demand() {
+    let max_len = NonZeroUsize::new(1000).unwrap();
+    let regular = LRUCache::<i32, i32>::with_max_len(max_len);
+    let mut lazy = LRUCache::with_max_len_lazy(max_len);
+    assert_eq!(lazy.capacity(), 0);
+
+    for i in 0..10 {
+        lazy.insert(i, i);
+    }
+    assert!(lazy.capacity() < regular.capacity());
+
+    for i in 10..1000 {
+        lazy.insert(i, i);
+    }
+    assert_eq!(lazy.len(), 1000);
+    assert_eq!(lazy.capacity(), regular.capacity());
+}
+
+#[test]
+fn pressure_fires_once_per_crossing() {
+    let fired = Rc::new(RefCell::new(Vec::new()));
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    {
+        let fired = fired.clone();
+        cache.on_pressure(0.75, move |len, max_len| {
+            fired.borrow_mut().push((len, max_len))
+        });
+    }
+
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    assert!(fired.borrow().is_empty());
+
+    cache.insert(3, 3);
+    cache.insert(4, 4);
+    cache.insert(5, 5);
+    assert_eq!(*fired.borrow(), vec![(3, 4)]);
+
+    // drops below the threshold, then crosses it again
+    cache.resize(NonZeroUsize::new(8).unwrap());
+    cache.insert(6, 6);
+    assert_eq!(fired.borrow().len(), 1);
+    cache.insert(7, 7);
+    assert_eq!(*fired.borrow(), vec![(3, 4), (6, 8)]);
+}
+
+#[test]
+fn restore_rolls_back_to_snapshot() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+    cache.insert(3, "three");
+    cache.get(&1);
+
+    let snapshot = cache.snapshot();
+    assert_eq!(snapshot.entries, vec![(2, "two"), (3, "three"), (1, "one")]);
+    assert_eq!(snapshot.max_len, 3);
+
+    cache.resize(NonZeroUsize::new(1).unwrap());
+    cache.insert(4, "four");
+    assert_eq!(cache.len(), 1);
+
+    cache.restore(snapshot);
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.max_len(), 3);
+
+    // 2 is still the least recently used one
+    cache.insert(5, "five");
+    assert_eq!(cache.get(&2), None);
+    assert_eq!(cache.get(&3), Some(&"three"));
+    assert_eq!(cache.get(&1), Some(&"one"));
+    assert_eq!(cache.get(&4), None);
+}
+
+#[test]
+fn growth_factor_keeps_behavior() {
+    let max_len = NonZeroUsize::new(1000).unwrap();
+    let mut cache = LRUCache::with_max_len_lazy(max_len).with_growth_factor(4.0);
+
+    let mut capacities = vec![cache.capacity()];
+    for i in 0..1000 {
+        cache.insert(i, i * 2);
+        if cache.capacity() != *capacities.last().unwrap() {
+            capacities.push(cache.capacity());
+        }
+    }
+    // grows at least 4 times per step, the last step is capped by `max_len()`
+    assert_eq!(
+        *capacities.last().unwrap(),
+        LRUCache::<i32, i32>::with_max_len(max_len).capacity()
+    );
+    let steps = capacities.len() - 1;
+    assert!(steps > 1);
+    for pair in capacities[..steps].windows(2) {
+        assert!(pair[1] >= pair[0] * 4);
+    }
+
+    for i in 1000..1500 {
+        cache.insert(i, i * 2);
+    }
+
+    assert_eq!(cache.len(), 1000);
+    assert_eq!(cache.get(&499), None);
+    assert_eq!(cache.get(&500), Some(&1000));
+    assert_eq!(cache.get(&1499), Some(&2998));
+}
+
+#[test]
+fn evict_until_stops_on_condition() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(5).unwrap());
+    for i in 0..5 {
+        cache.insert(i, i);
+    }
+    cache.get(&0);
+
+    assert_eq!(cache.evict_until(|cache| cache.len() <= 2), 3);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&4), Some(&4));
+    assert_eq!(cache.get(&0), Some(&0));
+
+    // stops on an empty cache
+    assert_eq!(cache.evict_until(|_| false), 2);
+    assert_eq!(cache.len(), 0);
+    assert_eq!(cache.evict_until(|_| false), 0);
+}
+
+#[test]
+fn entry_sizes_reports_every_entry() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert("short", String::from("ab"));
+    cache.insert("long", String::from("abcdef"));
+
+    let mut sizes: Vec<_> = cache
+        .entry_sizes(|key, val| key.len() + val.len())
+        .collect();
+    sizes.sort();
+    assert_eq!(sizes, vec![(&"long", 10), (&"short", 7)]);
+
+    // "short" is still the least recently used one
+    cache.insert("third", String::new());
+    cache.insert("fourth", String::new());
+    assert_eq!(cache.get(&"short"), None);
+}
+
+#[test]
+fn move_to_rank_repositions() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    for i in 0..4 {
+        cache.insert(i, i);
+    }
+    assert!(!cache.move_to_rank(&7, 0));
+
+    assert!(cache.move_to_rank(&3, 0));
+    assert!(cache.move_to_rank(&0, 100));
+    assert!(cache.move_to_rank(&1, 1));
+    assert_eq!(
+        cache.snapshot().entries,
+        vec![(3, 3), (1, 1), (2, 2), (0, 0)]
+    );
+
+    cache.insert(4, 4);
+    assert_eq!(cache.get(&3), None);
+}
+
+#[test]
+fn ranks_are_queried_by_borrowed_keys() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap())
+        .with_rank_index(NonZeroUsize::new(2).unwrap());
+    cache.insert(String::from("a"), 1);
+    cache.insert(String::from("b"), 2);
+    cache.insert(String::from("c"), 3);
+
+    assert_eq!(cache.rank_exact("a"), Some(0));
+    assert_eq!(cache.rank("c"), Some(2));
+    assert!(cache.insertion_seq("a") < cache.insertion_seq("b"));
+    assert!(cache.node_id("b").is_some());
+    assert!(cache.move_to_rank("c", 0));
+    assert_eq!(cache.rank_exact("c"), Some(0));
+    assert_eq!(cache.get_entry_cloned("a"), Some((String::from("a"), 1)));
+    assert_eq!(cache.node_id("d"), None);
+}
+
+#[test]
+fn copy_into_slice_keeps_hottest() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 'a');
+    cache.insert(2, 'b');
+    cache.insert(3, 'c');
+    cache.get(&1);
+
+    let mut short = [(0, '-'); 2];
+    assert_eq!(cache.copy_into_slice(&mut short), 2);
+    assert_eq!(short, [(1, 'a'), (3, 'c')]);
+
+    let mut long = [(0, '-'); 4];
+    assert_eq!(cache.copy_into_slice(&mut long), 3);
+    assert_eq!(long, [(1, 'a'), (3, 'c'), (2, 'b'), (0, '-')]);
+}
+
+#[test]
+fn remove_returning_next_walks_recency() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+    cache.insert(3, "three");
+
+    assert_eq!(cache.remove_returning_next(&4), None);
+    assert_eq!(cache.remove_returning_next(&2), Some((2, "two", Some(3))));
+    assert_eq!(cache.remove_returning_next(&3), Some((3, "three", None)));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.get(&1), Some(&"one"));
+}
+
+#[test]
+fn miss_counter_is_shared() {
+    let misses = Rc::new(Cell::new(0));
+    let mut first =
+        LRUCache::with_max_len(NonZeroUsize::new(2).unwrap()).with_miss_counter(misses.clone());
+    let mut second =
+        LRUCache::with_max_len(NonZeroUsize::new(2).unwrap()).with_miss_counter(misses.clone());
+
+    first.insert(1, 1);
+    second.insert(2, 2);
+
+    assert_eq!(first.get(&1), Some(&1));
+    assert_eq!(second.get(&2), Some(&2));
+    assert_eq!(misses.get(), 0);
+
+    assert_eq!(first.get(&2), None);
+    assert_eq!(second.get(&1), None);
+    assert_eq!(misses.get(), 2);
+
+    assert_eq!(first.peek(&1), Some(&1));
+    assert_eq!(first.peek(&2), None);
+    assert_eq!(second.peek_or(&1, &0), &0);
+    assert_eq!(misses.get(), 4);
+}
+
+#[test]
+fn repair_keeps_consistent_cache_intact() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    cache.insert(3, 3);
+    cache.get(&1);
+    let before = cache.snapshot();
+
+    cache.repair();
+
+    assert_eq!(cache.snapshot(), before);
+    cache.insert(4, 4);
+    assert_eq!(cache.get(&2), None);
+}
+
+#[test]
+fn get_or_insert_checked_rejects_when_full() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+
+    assert_eq!(cache.get_or_insert_checked(1, || "one"), Ok(&"one"));
+    assert_eq!(cache.get_or_insert_checked(2, || "two"), Ok(&"two"));
+    assert_eq!(
+        cache.get_or_insert_checked(1, || unreachable!()),
+        Ok(&"one")
+    );
+
+    assert_eq!(
+        cache.get_or_insert_checked(3, || unreachable!()),
+        Err(CapacityError)
+    );
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&2), Some(&"two"));
+}
+
+#[test]
+fn touch_matching_protects_namespace() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    cache.insert(("users", 1), 'a');
+    cache.insert(("posts", 1), 'b');
+    cache.insert(("users", 2), 'c');
+    cache.insert(("posts", 2), 'd');
+
+    assert_eq!(
+        cache.touch_matching(|(namespace, _)| *namespace == "users"),
+        2
+    );
+    assert_eq!(
+        cache.snapshot().entries,
+        vec![
+            (("posts", 1), 'b'),
+            (("posts", 2), 'd'),
+            (("users", 1), 'a'),
+            (("users", 2), 'c')
+        ]
+    );
+    assert_eq!(cache.touch_matching(|_| false), 0);
+}
+
+#[test]
+fn into_sorted_by_ignores_recency() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert("b", 2);
+    cache.insert("c", 1);
+    cache.insert("a", 3);
+
+    let by_key = cache.into_sorted_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+    assert_eq!(by_key, vec![("a", 3), ("b", 2), ("c", 1)]);
+}
+
+#[test]
+fn peek_or_does_not_promote() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+
+    assert_eq!(cache.peek_or(&1, &"none"), &"one");
+    assert_eq!(cache.peek_or(&3, &"none"), &"none");
+
+    // 1 is still the least recently used one
+    cache.insert(3, "three");
+    assert_eq!(cache.peek_or(&1, &"none"), &"none");
+}
+
+#[test]
+fn resize_to_len_freezes_working_set() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(10).unwrap());
+    assert!(cache.resize_to_len().is_empty());
+    assert_eq!(cache.max_len(), 10);
+
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    assert!(cache.resize_to_len().is_empty());
+    assert_eq!(cache.max_len(), 2);
+
+    cache.insert(3, 3);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&1), None);
+}
+
+#[test]
+fn diagnostics_report() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+
+    let diagnostics = cache.diagnostics();
+    assert_eq!(diagnostics.len, 2);
+    assert_eq!(diagnostics.max_len, 4);
+    assert!(diagnostics.capacity >= 4);
+    assert_eq!(diagnostics.queue_len, 2);
+    assert!(diagnostics.consistent);
+    assert!(diagnostics.to_string().starts_with("len 2/4, capacity "));
+}
+
+#[test]
+fn eviction_history_keeps_last_keys() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap()).with_eviction_history(2);
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    assert_eq!(cache.recent_evictions().count(), 0);
+
+    // replacement is not an eviction
+    cache.insert(2, 3);
+    cache.insert(3, 3);
+    cache.insert(4, 4);
+    assert_eq!(cache.recent_evictions().collect::<Vec<_>>(), vec![&1, &2]);
+
+    cache.resize(NonZeroUsize::new(1).unwrap());
+    assert_eq!(cache.recent_evictions().collect::<Vec<_>>(), vec![&2, &3]);
+}
+
+#[test]
+fn get_cloned_or_insert_with_releases_borrow() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+
+    let first = cache.get_cloned_or_insert_with("a", || String::from("computed"));
+    // the cache can be mutated while `first` is alive
+    cache.insert("b", String::from("b"));
+    let second = cache.get_cloned_or_insert_with("a", || unreachable!());
+    assert_eq!(first, second);
+
+    // "a" was promoted, so "b" gets evicted
+    cache.insert("c", String::from("c"));
+    assert_eq!(cache.get(&"b"), None);
+}
+
+#[test]
+fn rank_index_is_refreshed_lazily() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap())
+        .with_rank_index(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    cache.insert(3, 3);
+    assert_eq!(cache.rank(&4), None);
+
+    assert_eq!(cache.rank(&1), Some(0));
+    cache.get(&1);
+    // served from the index built by the previous query
+    assert_eq!(cache.rank(&1), Some(0));
+    assert_eq!(cache.rank_exact(&1), Some(2));
+    // the index is rebuilt after two queries
+    assert_eq!(cache.rank(&1), Some(2));
+    assert_eq!(cache.rank(&2), Some(0));
+}
+
+#[cfg(feature = "spill")]
+#[test]
+fn spill_oldest_streams_cold_entries() {
+    use std::sync::mpsc::channel;
+
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    for i in 0..4 {
+        cache.insert(i, i * 10);
+    }
+
+    let (tx, rx) = channel();
+    assert_eq!(cache.spill_oldest(2, &tx), 2);
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![(0, 0), (1, 10)]);
+    assert_eq!(cache.len(), 2);
+
+    drop(rx);
+    assert_eq!(cache.spill_oldest(2, &tx), 0);
+    assert_eq!(cache.len(), 2);
+    // the unsent entry is still the least recently used one
+    assert_eq!(cache.snapshot().entries, vec![(2, 20), (3, 30)]);
+}
+
+#[test]
+fn read_validator_rejects_stale_entries() {
+    let min_version = Rc::new(Cell::new(0));
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap()).with_read_validator({
+        let min_version = min_version.clone();
+        move |_, (version, _)| *version >= min_version.get()
+    });
+    cache.insert("a", (0, 'a'));
+    cache.insert("b", (1, 'b'));
+    cache.insert("c", (1, 'c'));
+
+    min_version.set(1);
+    assert_eq!(cache.get(&"b"), Some(&(1, 'b')));
+    assert_eq!(cache.peek_or(&"a", &(9, '-')), &(9, '-'));
+    assert_eq!(cache.get(&"c"), Some(&(1, 'c')));
+    // stays counted until found by a promoting lookup
+    assert_eq!(cache.len(), 3);
+
+    // "a" was rejected, so it's removed
+    assert_eq!(cache.get(&"a"), None);
+    assert_eq!(cache.len(), 2);
+    cache.insert("d", (1, 'd'));
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.get(&"b"), Some(&(1, 'b')));
+    assert_eq!(
+        cache.get_or_insert_checked("a", || (1, 'A')),
+        Err(CapacityError)
+    );
+}
+
+#[test]
+fn insert_growing_prefers_growth() {
+    let ceiling = NonZeroUsize::new(3).unwrap();
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(1).unwrap());
+
+    assert_eq!(cache.insert_growing(1, "one", ceiling), None);
+    assert_eq!(cache.insert_growing(2, "two", ceiling), None);
+    assert_eq!(cache.insert_growing(3, "three", ceiling), None);
+    assert_eq!(cache.max_len(), 3);
+    assert_eq!(cache.len(), 3);
+
+    assert_eq!(cache.insert_growing(2, "TWO", ceiling), Some((2, "two")));
+    assert_eq!(cache.insert_growing(4, "four", ceiling), Some((1, "one")));
+    assert_eq!(cache.max_len(), 3);
+}
+
+#[test]
+fn checked_insert_on_consistent_cache() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+
+    assert_eq!(cache.checked_insert(1, 1), Ok(None));
+    assert_eq!(cache.checked_insert(2, 2), Ok(None));
+    assert_eq!(cache.checked_insert(1, 3), Ok(Some((1, 1))));
+    assert_eq!(cache.checked_insert(3, 3), Ok(None));
+    assert_eq!(cache.get(&2), None);
+
+    let error = CacheError::LengthExceeded { len: 3, max_len: 2 };
+    assert_eq!(
+        error.to_string(),
+        "cache holds 3 entries, while at most 2 are allowed"
+    );
+}
+
+#[test]
+fn compact_preserves_contents() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(100).unwrap());
+    for i in 0..100 {
+        cache.insert(i, i);
+    }
+    cache.evict_until(|cache| cache.len() == 3);
+    cache.get(&97);
+    let before = cache.snapshot();
+    let capacity_before = cache.diagnostics().capacity;
+
+    cache.compact();
+
+    assert!(cache.diagnostics().capacity < capacity_before);
+    assert_eq!(cache.snapshot(), before);
+}
+
+#[test]
+fn get_mut_or_default_no_promote_accumulates() {
+    let mut counters = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+
+    *counters.get_mut_or_default_no_promote("a") += 1;
+    *counters.get_mut_or_default_no_promote("b") += 1;
+    *counters.get_mut_or_default_no_promote("a") += 1;
+    assert_eq!(counters.peek_or(&"a", &0), &2);
+
+    // updating "a" didn't promote it
+    *counters.get_mut_or_default_no_promote("c") += 5;
+    assert_eq!(counters.peek_or(&"a", &0), &0);
+    assert_eq!(counters.peek_or(&"b", &0), &1);
+    assert_eq!(counters.peek_or(&"c", &0), &5);
+
+    let mut counters = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap())
+        .with_read_validator(|_, val| *val >= 0);
+    counters.insert("a", -1);
+    *counters.get_mut_or_default_no_promote("a") += 1;
+    assert_eq!(counters.peek(&"a"), Some(&1));
+    assert_eq!(counters.len(), 1);
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn reuse_distance_histogram_counts_distinct_keys() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+
+    for key in [1, 2, 3, 1, 1, 2] {
+        cache.get(&key);
+    }
+
+    // 1: after 2 and 3, then right away; 2: after 3 and 1
+    assert_eq!(cache.reuse_distance_histogram(), vec![1, 0, 2]);
+}
+
+#[test]
+fn len_and_capacity_matches_accessors() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(8).unwrap());
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+
+    let (len, max_len, capacity) = cache.len_and_capacity();
+    assert_eq!(len, cache.len());
+    assert_eq!(max_len, cache.max_len());
+    assert_eq!(capacity, cache.diagnostics().capacity);
+    assert!(capacity >= max_len);
+}
+
+#[test]
+fn scoped_insert_removes_on_drop() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert("long-lived", 1);
+
+    {
+        let mut scoped = cache.scoped_insert("request", 2);
+        assert_eq!(scoped.key(), &"request");
+        assert_eq!(scoped.get(&"request"), Some(&2));
+        scoped.insert("other", 3);
+    }
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&"request"), None);
+
+    cache.scoped_insert("kept", 4).keep();
+    assert_eq!(cache.get(&"kept"), Some(&4));
+
+    // a replaced entry is not removed by the guard
+    {
+        let mut scoped = cache.scoped_insert("replaced", 5);
+        scoped.insert("replaced", 6);
+    }
+    assert_eq!(cache.get(&"replaced"), Some(&6));
+}
+
+#[test]
+fn adopt_recency_from_reorders_only() {
+    let mut restored = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    for key in ["a", "b", "c", "d"] {
+        restored.insert(key, key.len());
+    }
+
+    let mut persisted = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    for key in ["x", "c", "a"] {
+        persisted.insert(key, 0);
+    }
+
+    restored.adopt_recency_from(&persisted);
+    assert_eq!(
+        restored.snapshot().entries,
+        vec![("c", 1), ("a", 1), ("b", 1), ("d", 1)]
+    );
+}
+
+#[test]
+fn contains_key_fresh_tells_stale_entries() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap())
+        .with_read_validator(|_, val| *val > 0);
+    cache.insert("fresh", 1);
+    cache.insert("stale", 0);
+
+    assert!(cache.contains_key_fresh(&"fresh"));
+    assert!(!cache.contains_key_fresh(&"stale"));
+    assert!(!cache.contains_key_fresh(&"absent"));
+    assert_eq!(cache.len(), 2);
+
+    // "fresh" is still the least recently used one
+    cache.insert("new", 2);
+    assert!(!cache.contains_key_fresh(&"fresh"));
+}
+
+#[test]
+fn get_many_cloned_promotes_in_order() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, String::from("one"));
+    cache.insert(2, String::from("two"));
+    cache.insert(3, String::from("three"));
+
+    let values = cache.get_many_cloned(&[2, 4, 1]);
+    assert_eq!(
+        values,
+        vec![Some(String::from("two")), None, Some(String::from("one"))]
+    );
+
+    // the cache is free to mutate while `values` is alive
+    cache.insert(5, String::from("five"));
+    assert_eq!(cache.get(&3), None);
+    assert_eq!(values.len(), 3);
+}
+
+#[derive(Debug, Clone)]
+struct CaseInsensitive(&'static str);
+
+impl PartialEq for CaseInsensitive {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_ignore_ascii_case(other.0)
+    }
+}
+
+impl Eq for CaseInsensitive {}
+
+impl std::hash::Hash for CaseInsensitive {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_ascii_lowercase().hash(state);
+    }
+}
+
+#[test]
+fn get_entry_cloned_returns_canonical_key() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(CaseInsensitive("Content-Type"), 1);
+    cache.insert(CaseInsensitive("Accept"), 2);
+
+    let (key, val) = cache
+        .get_entry_cloned(&CaseInsensitive("content-type"))
+        .unwrap();
+    assert_eq!(key.0, "Content-Type");
+    assert_eq!(val, 1);
+    assert_eq!(
+        cache
+            .get_entry_cloned(&CaseInsensitive("host"))
+            .map(|(_, val)| val),
+        None
+    );
+
+    // "Content-Type" was promoted
+    cache.insert(CaseInsensitive("Host"), 3);
+    assert_eq!(cache.get(&CaseInsensitive("accept")), None);
+}
+
+#[test]
+fn trim_to_bytes_evicts_oldest() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    cache.insert(1, vec![0u8; 10]);
+    cache.insert(2, vec![0u8; 30]);
+    cache.insert(3, vec![0u8; 20]);
+    cache.insert(4, vec![0u8; 5]);
+    cache.get(&1);
+
+    let calls = Cell::new(0);
+    let evicted = cache.trim_to_bytes(30, |_, val| {
+        calls.set(calls.get() + 1);
+        val.len()
+    });
+    let evicted_keys: Vec<_> = evicted.iter().map(|(key, _)| *key).collect();
+    assert_eq!(evicted_keys, vec![2, 3]);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(calls.get(), 4);
+
+    assert!(cache.trim_to_bytes(15, |_, val| val.len()).is_empty());
+    assert_eq!(cache.trim_to_bytes(0, |_, val| val.len()).len(), 2);
+}
+
+#[test]
+fn iter_filter_by_value() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    for i in 0..4 {
+        cache.insert(i, i * 10);
+    }
+
+    let mut big: Vec<_> = cache.iter_filter(|_, val| *val >= 20).collect();
+    big.sort();
+    assert_eq!(big, vec![(&2, &20), (&3, &30)]);
+
+    // 0 is still the least recently used one
+    cache.insert(4, 40);
+    assert_eq!(cache.get(&0), None);
+}
+
+#[test]
+fn upsert_modifies_or_creates() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+
+    assert_eq!(cache.upsert("a", |count| *count += 1, || 1), &1);
+    assert_eq!(cache.upsert("b", |_| unreachable!(), || 10), &10);
+    assert_eq!(
+        cache.upsert("a", |count| *count += 1, || unreachable!()),
+        &2
+    );
+
+    // "a" was promoted, "b" gets evicted
+    cache.upsert("c", |_| unreachable!(), || 0);
+    assert_eq!(cache.get(&"b"), None);
+    assert_eq!(cache.get(&"a"), Some(&2));
+}
+
+#[test]
+fn try_resize_min_guards_floor() {
+    let floor = NonZeroUsize::new(2).unwrap();
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    cache.insert(3, 3);
+
+    let error = cache.try_resize_min(NonZeroUsize::new(1).unwrap(), floor);
+    assert_eq!(
+        error,
+        Err(ResizeError {
+            requested: NonZeroUsize::new(1).unwrap(),
+            floor
+        })
+    );
+    assert_eq!(cache.max_len(), 3);
+    assert_eq!(cache.len(), 3);
+
+    assert_eq!(cache.try_resize_min(floor, floor), Ok(vec![(1, 1)]));
+    assert_eq!(cache.max_len(), 2);
+}
+
+#[test]
+fn purge_removes_matching_and_shrinks() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(64).unwrap());
+    for i in 0..64 {
+        cache.insert(i, i * 10);
+    }
+    cache.get(&0);
+
+    let capacity = cache.len_and_capacity().2;
+    assert_eq!(cache.purge(|key, val| key % 4 != 0 && *val != 10), 47);
+    assert_eq!(cache.len(), 17);
+    assert!(cache.len_and_capacity().2 < capacity);
+
+    let order: Vec<_> = cache
+        .snapshot()
+        .entries
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    let mut expected: Vec<_> = (4..64).step_by(4).collect();
+    expected.insert(0, 1);
+    expected.push(0);
+    assert_eq!(order, expected);
+
+    assert_eq!(cache.purge(|key, _| *key == 0), 1);
+    assert_eq!(cache.len(), 16);
+}
+
+#[test]
+fn remove_deletes_single_entry() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+
+    assert_eq!(cache.remove(&1), Some("one"));
+    assert_eq!(cache.remove(&1), None);
+    assert_eq!(cache.len(), 1);
+    assert!(cache.get(&1).is_none());
+
+    cache.insert(3, "three");
+    assert_eq!(cache.len(), 2);
+    assert_eq!(*cache.get(&2).unwrap(), "two");
+}
+
+#[test]
+fn recency_bounds_reports_both_ends() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    assert_eq!(cache.recency_bounds(), None);
+
+    cache.insert(1, ());
+    assert_eq!(cache.recency_bounds(), Some(((&1, 0), (&1, 0))));
+
+    cache.insert(2, ());
+    cache.insert(3, ());
+    cache.get(&1);
+    assert_eq!(cache.recency_bounds(), Some(((&2, 0), (&1, 2))));
+    assert_eq!(cache.recency_bounds(), Some(((&2, 0), (&1, 2))));
+}
+
+#[test]
+fn peek_keeps_recency() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+
+    assert_eq!(cache.peek(&1), Some(&"one"));
+    assert_eq!(cache.peek(&3), None);
+
+    cache.insert(3, "three");
+    assert_eq!(cache.peek(&1), None);
+    assert_eq!(cache.peek(&2), Some(&"two"));
+}
+
+#[test]
+fn peek_and_pop_lru() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    assert_eq!(cache.peek_lru(), None);
+    assert_eq!(cache.pop_lru(), None);
+
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+    cache.insert(3, "three");
+    cache.get(&1);
+
+    assert_eq!(cache.peek_lru(), Some((&2, &"two")));
+    assert_eq!(cache.peek_lru(), Some((&2, &"two")));
+
+    assert_eq!(cache.pop_lru(), Some((2, "two")));
+    assert_eq!(cache.pop_lru(), Some((3, "three")));
+    assert_eq!(cache.pop_lru(), Some((1, "one")));
+    assert_eq!(cache.pop_lru(), None);
+    assert_eq!(cache.len(), 0);
+}
+
+#[test]
+fn get_mut_updates_value_and_recency() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert("a", 1);
+    cache.insert("b", 10);
+
+    *cache.get_mut(&"a").unwrap() += 1;
+    assert!(cache.get_mut(&"c").is_none());
+
+    cache.insert("c", 100);
+    assert_eq!(cache.peek(&"b"), None);
+    assert_eq!(cache.peek(&"a"), Some(&2));
+}
+
+#[test]
+fn clear_empties_cache() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+
+    cache.clear();
+    assert_eq!(cache.len(), 0);
+    assert_eq!(cache.max_len(), 2);
+    assert!(cache.get(&1).is_none());
+    assert!(cache.iter().next().is_none());
+
+    cache.insert(3, 3);
+    cache.insert(4, 4);
+    cache.insert(5, 5);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.peek_lru(), Some((&4, &4)));
+}
+
+#[test]
+fn contains_key_borrows_and_keeps_recency() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert("a".to_string(), 1);
+    cache.insert("b".to_string(), 2);
+
+    assert!(cache.contains_key("a"));
+    assert!(!cache.contains_key("c"));
+
+    cache.insert("c".to_string(), 3);
+    assert!(!cache.contains_key("a"));
+    assert!(cache.contains_key("b"));
+}
+
+#[test]
+fn lookups_accept_borrowed_keys() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert("a".to_string(), 1);
+    cache.insert("b".to_string(), 2);
+
+    assert_eq!(cache.get("a"), Some(&1));
+    assert_eq!(cache.peek("b"), Some(&2));
+    *cache.get_mut("b").unwrap() += 1;
+    assert_eq!(cache.remove("a"), Some(1));
+    assert_eq!(cache.remove("a"), None);
+    assert_eq!(cache.get("b"), Some(&3));
+}
+
+#[derive(Default)]
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[test]
+fn custom_hasher() {
+    let hasher = BuildHasherDefault::<Fnv>::default();
+    let mut cache = LRUCache::with_hasher(NonZeroUsize::new(2).unwrap(), hasher);
+    cache.insert("a".to_string(), 1);
+    cache.insert("b".to_string(), 2);
+    cache.get("a");
+    cache.insert("c".to_string(), 3);
+
+    assert_eq!(cache.peek("a"), Some(&1));
+    assert_eq!(cache.peek("b"), None);
+
+    let mut defaulted: LRUCache<u32, u32, BuildHasherDefault<Fnv>> = LRUCache::default();
+    defaulted.insert(1, 1);
+    assert_eq!(defaulted.get(&1), Some(&1));
+}
+
+#[test]
+fn entry_inserts_or_modifies() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    *cache.entry("a").or_insert(0) += 1;
+    cache.entry("b").or_insert_with(|| 10);
+    cache
+        .entry("a")
+        .and_modify(|val| *val += 1)
+        .and_modify(|val| *val *= 10)
+        .or_insert(0);
+    assert_eq!(cache.peek(&"a"), Some(&20));
+
+    cache
+        .entry("c")
+        .and_modify(|_| panic!("absent"))
+        .or_insert(30);
+    assert_eq!(cache.peek(&"b"), None);
+    assert_eq!(cache.peek(&"a"), Some(&20));
+    assert_eq!(cache.peek(&"c"), Some(&30));
+}
+
+#[test]
+fn occupied_entry_accessors() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+
+    let Entry::Occupied(mut occupied) = cache.entry("a") else {
+        panic!("a is stored");
+    };
+    assert_eq!(occupied.key(), &"a");
+    assert_eq!(occupied.get(), &1);
+    *occupied.get_mut() += 1;
+    assert_eq!(occupied.insert(10), 2);
+    assert_eq!(*occupied.into_mut(), 10);
+    // promoted by `entry` only
+    assert_eq!(cache.stats().hits, 1);
+    assert_eq!(cache.peek_lru(), Some((&"b", &2)));
+
+    let Entry::Occupied(occupied) = cache.entry("b") else {
+        panic!("b is stored");
+    };
+    assert_eq!(occupied.remove_entry(), ("b", 2));
+    let Entry::Occupied(occupied) = cache.entry("c") else {
+        panic!("c is stored");
+    };
+    assert_eq!(occupied.remove(), 3);
+    assert_eq!(cache.len(), 1);
+
+    let vacant = cache.entry("d");
+    assert_eq!(vacant.key(), &"d");
+    let Entry::Vacant(vacant) = vacant else {
+        panic!("d is absent");
+    };
+    assert_eq!(vacant.into_key(), "d");
+    assert_eq!(cache.iter_lru().collect::<Vec<_>>(), vec![(&"a", &10)]);
+}
+
+#[test]
+fn get_or_insert_with_computes_on_miss_only() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    let calls = Cell::new(0);
+    let compute = |val| {
+        calls.set(calls.get() + 1);
+        move || val
+    };
+
+    assert_eq!(*cache.get_or_insert_with(1, compute(10)), 10);
+    assert_eq!(*cache.get_or_insert_with(2, compute(20)), 20);
+    assert_eq!(*cache.get_or_insert_with(1, || panic!("hit")), 10);
+    assert_eq!(calls.get(), 2);
+
+    assert_eq!(*cache.get_or_insert_with(3, compute(30)), 30);
+    assert_eq!(cache.peek(&2), None);
+    assert_eq!(cache.peek(&1), Some(&10));
+}
+
+#[test]
+fn entry_replaces_rejected_values() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap())
+        .with_read_validator(|_, val| *val >= 0);
+
+    cache.insert("k", -1);
+    assert_eq!(cache.upsert("k", |val| *val = 99, || 1), &1);
+    cache.insert("k", -1);
+    assert_eq!(*cache.get_or_insert_with("k", || 2), 2);
+    cache.insert("k", -1);
+    assert_eq!(*cache.get_or_insert("k", 3), 3);
+    cache.insert("k", -1);
+    assert_eq!(cache.get_or_try_insert_with("k", || Ok::<_, ()>(4)), Ok(&4));
+    assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 4 });
+
+    assert_eq!(*cache.get_or_insert_with("k", || panic!("hit")), 4);
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 4 });
+}
+
+#[test]
+fn failed_try_insert_leaves_cache_untouched() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+
+    assert_eq!(
+        cache.get_or_try_insert_with(3, || Err("unavailable")),
+        Err("unavailable")
+    );
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.peek_lru(), Some((&1, &10)));
+
+    assert_eq!(
+        cache.get_or_try_insert_with(1, || Err("unavailable")),
+        Ok(&10)
+    );
+    assert_eq!(cache.get_or_try_insert_with(3, || Ok::<_, ()>(30)), Ok(&30));
+    assert_eq!(cache.peek(&2), None);
+}
+
+#[test]
+fn into_iter_yields_lru_to_mru() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+    cache.insert(3, "three");
+    cache.get(&1);
+
+    let pairs = cache.into_iter();
+    assert_eq!(pairs.len(), 3);
+    assert_eq!(
+        pairs.collect::<Vec<_>>(),
+        vec![(2, "two"), (3, "three"), (1, "one")]
+    );
+}
+
+#[test]
+fn iterates_in_recency_order() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+    cache.get(&1);
+
+    let lru: Vec<_> = cache.iter_lru().map(|(key, _)| *key).collect();
+    assert_eq!(lru, vec![2, 3, 1]);
+    let mru: Vec<_> = cache.iter_mru().collect();
+    assert_eq!(mru, vec![(&1, &10), (&3, &30), (&2, &20)]);
+    assert_eq!(cache.iter_lru().next_back(), Some((&1, &10)));
+    assert_eq!(cache.peek_lru(), Some((&2, &20)));
+}
+
+#[test]
+fn collects_without_evicting() {
+    let cache: LRUCache<_, _> = vec![(1, "a"), (2, "b"), (1, "c"), (3, "d")]
+        .into_iter()
+        .collect();
+    assert_eq!(cache.max_len(), 3);
+    assert_eq!(
+        cache.into_iter().collect::<Vec<_>>(),
+        vec![(2, "b"), (1, "c"), (3, "d")]
+    );
+
+    let empty: LRUCache<u32, u32> = std::iter::empty().collect();
+    assert_eq!(empty.max_len(), 1);
+    assert_eq!(empty.len(), 0);
+}
+
+#[test]
+fn drain_empties_cache() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+
+    assert_eq!(
+        cache.drain().collect::<Vec<_>>(),
+        vec![(1, 10), (2, 20), (3, 30)]
+    );
+    assert_eq!(cache.len(), 0);
+    assert_eq!(cache.max_len(), 3);
+
+    cache.insert(4, 40);
+    cache.insert(5, 50);
+    assert_eq!(cache.drain().next(), Some((4, 40)));
+    assert_eq!(cache.len(), 0);
+    assert!(cache.get(&5).is_none());
+}
+
+#[test]
+fn retain_keeps_matching_in_order() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(5).unwrap());
+    for i in 0..5 {
+        cache.insert(i, i * 10);
+    }
+    cache.get(&0);
+
+    cache.retain(|key, val| key % 2 == 0 || *val == 10);
+    assert_eq!(cache.len(), 4);
+    let order: Vec<_> = cache.iter_lru().map(|(key, _)| *key).collect();
+    assert_eq!(order, vec![1, 2, 4, 0]);
+
+    cache.retain(|_, _| false);
+    assert_eq!(cache.len(), 0);
+}
+
+#[test]
+fn stats_count_hits_and_misses() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, 10);
+    cache.get(&1);
+    cache.get(&1);
+    cache.get(&2);
+    cache.peek(&2);
+    cache.contains_key(&1);
+
+    let stats = cache.stats();
+    assert_eq!(stats, CacheStats { hits: 2, misses: 1 });
+    assert!((stats.hit_ratio() - 2.0 / 3.0).abs() < f64::EPSILON);
+
+    cache.reset_stats();
+    assert_eq!(cache.stats(), CacheStats::default());
+    assert_eq!(cache.stats().hit_ratio(), 0.0);
+}
+
+#[test]
+fn eviction_listener_takes_evicted_pairs() {
+    let evicted = Rc::new(RefCell::new(Vec::new()));
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    let listened = evicted.clone();
+    cache.on_evict(move |key, val, reason| listened.borrow_mut().push((key, val, reason)));
+
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+    cache.insert(1, 11);
+    cache.remove(&2);
+    assert!(evicted.borrow().is_empty());
+
+    cache.insert(4, 40);
+    cache.insert(5, 50);
+    assert_eq!(cache.resize(NonZeroUsize::new(1).unwrap()), vec![]);
+    assert_eq!(
+        *evicted.borrow(),
+        vec![
+            (3, 30, EvictionReason::Capacity),
+            (1, 11, EvictionReason::Resize),
+            (4, 40, EvictionReason::Resize),
+        ]
+    );
+}
+
+#[test]
+fn eviction_listener_agrees_with_history() {
+    let evicted = Rc::new(RefCell::new(Vec::new()));
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap()).with_eviction_history(8);
+    let listened = evicted.clone();
+    cache.on_evict(move |key, val, reason| listened.borrow_mut().push((key, val, reason)));
+
+    let ceiling = NonZeroUsize::new(2).unwrap();
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    assert_eq!(cache.insert_growing(3, 30, ceiling), None);
+    assert_eq!(cache.evict_until(|cache| cache.len() == 1), 1);
+    cache.insert(4, 40);
+    assert_eq!(cache.trim_to_bytes(1, |_, _| 1), vec![]);
+
+    assert_eq!(
+        *evicted.borrow(),
+        vec![
+            (1, 10, EvictionReason::Capacity),
+            (2, 20, EvictionReason::Trim),
+            (3, 30, EvictionReason::Trim),
+        ]
+    );
+    assert_eq!(
+        cache.recent_evictions().collect::<Vec<_>>(),
+        vec![&1, &2, &3]
+    );
+}
+
+#[cfg(feature = "ttl")]
+#[derive(Clone)]
+struct ManualClock(Rc<Cell<std::time::Instant>>);
+
+#[cfg(feature = "ttl")]
+impl lru_cache::Clock for ManualClock {
+    fn now(&self) -> std::time::Instant {
+        self.0.get()
+    }
+}
+
+#[cfg(feature = "ttl")]
+#[test]
+fn entries_expire_after_write() {
+    use std::time::{Duration, Instant};
+
+    let clock = ManualClock(Rc::new(Cell::new(Instant::now())));
+    let advance = |secs| clock.0.set(clock.0.get() + Duration::from_secs(secs));
+    let mut cache = LRUCache::with_ttl(NonZeroUsize::new(3).unwrap(), Duration::from_secs(10))
+        .with_clock(clock.clone());
+
+    cache.insert(1, 10);
+    advance(6);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+    cache.get(&1);
+    advance(6);
+
+    assert_eq!(cache.get(&1), None);
+    assert_eq!(cache.get(&2), Some(&20));
+    assert_eq!(cache.peek_lru(), Some((&3, &30)));
+    assert_eq!(cache.len(), 2);
+
+    cache.purge_expired();
+    assert_eq!(cache.len(), 2);
+    advance(5);
+    cache.purge_expired();
+    assert_eq!(cache.len(), 0);
+}
+
+#[cfg(feature = "ttl")]
+#[test]
+fn insert_dedup_refreshes_expired_values() {
+    use std::time::{Duration, Instant};
+
+    let clock = ManualClock(Rc::new(Cell::new(Instant::now())));
+    let mut cache = LRUCache::with_ttl(NonZeroUsize::new(2).unwrap(), Duration::from_secs(10))
+        .with_clock(clock.clone());
+
+    cache.insert(1, 10);
+    clock.0.set(clock.0.get() + Duration::from_secs(11));
+    cache.insert_dedup(1, 10);
+    assert_eq!(cache.get(&1), Some(&10));
+}
+
+#[cfg(feature = "ttl")]
+#[test]
+fn entry_replaces_expired_values() {
+    use std::time::{Duration, Instant};
+
+    let clock = ManualClock(Rc::new(Cell::new(Instant::now())));
+    let advance = |secs| clock.0.set(clock.0.get() + Duration::from_secs(secs));
+    let mut cache = LRUCache::with_ttl(NonZeroUsize::new(2).unwrap(), Duration::from_secs(10))
+        .with_clock(clock.clone());
+
+    cache.insert("k", 1);
+    advance(11);
+    assert_eq!(cache.get(&"k"), None);
+    cache.insert("k", 1);
+    advance(11);
+    assert_eq!(*cache.get_or_insert_with("k", || 2), 2);
+    advance(11);
+    assert_eq!(cache.upsert("k", |val| *val += 10, || 3), &3);
+    assert_eq!(cache.upsert("k", |val| *val += 10, || 3), &13);
+}
+
+#[cfg(feature = "ttl")]
+#[test]
+fn merge_with_does_not_revive_expired_values() {
+    use std::time::{Duration, Instant};
+
+    let clock = ManualClock(Rc::new(Cell::new(Instant::now())));
+    let mut cache = LRUCache::with_ttl(NonZeroUsize::new(2).unwrap(), Duration::from_secs(10))
+        .with_clock(clock.clone());
+    cache.insert("stale", 1);
+    cache.insert("fresh", 1);
+    clock.0.set(clock.0.get() + Duration::from_secs(11));
+    cache.insert("fresh", 1);
+
+    let mut shard = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    shard.insert("stale", 1);
+    shard.insert("fresh", 1);
+    cache.merge_with(shard, |existing, incoming| *existing += incoming);
+
+    assert_eq!(cache.get(&"stale"), Some(&1));
+    assert_eq!(cache.get(&"fresh"), Some(&2));
+}
+
+#[test]
+fn disabled_cache_stores_nothing() {
+    let mut cache = LRUCache::with_max_len_or_disabled(0);
+    assert_eq!(cache.max_len(), 0);
+    assert_eq!(cache.insert(1, 10), Some((1, 10)));
+    assert_eq!(
+        cache.insert_growing(2, 20, NonZeroUsize::new(4).unwrap()),
+        Some((2, 20))
+    );
+    assert_eq!(cache.checked_insert(3, 30), Ok(Some((3, 30))));
+    assert_eq!(cache.get(&1), None);
+    assert_eq!(cache.len(), 0);
+    assert_eq!(cache.get_or_insert_checked(1, || 10), Err(CapacityError));
+    drop(cache.scoped_insert(3, 30));
+
+    cache.resize(NonZeroUsize::new(1).unwrap());
+    cache.insert(1, 10);
+    assert_eq!(cache.get(&1), Some(&10));
+
+    let enabled = LRUCache::<u32, u32>::with_max_len_or_disabled(2);
+    assert_eq!(enabled.max_len(), 2);
+}
+
+#[test]
+#[should_panic(expected = "disabled")]
+fn disabled_cache_has_no_entry_to_refer_to() {
+    let mut cache = LRUCache::with_max_len_or_disabled(0);
+    cache.get_or_insert_with(1, || 10);
+}
+
+#[test]
+fn values_need_not_be_debug() {
+    #[derive(PartialEq)]
+    struct Opaque(u32);
+
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(1).unwrap());
+    assert!(cache.insert(1, Opaque(1)).is_none());
+    let replaced = cache.insert(1, Opaque(2));
+    assert!(replaced.is_some_and(|(key, val)| key == 1 && val == Opaque(1)));
+
+    cache.resize(NonZeroUsize::new(2).unwrap());
+    cache.insert(2, Opaque(3));
+    let evicted = cache.resize(NonZeroUsize::new(1).unwrap());
+    assert!(evicted.len() == 1 && evicted[0].1 == Opaque(2));
+    assert!(cache.get(&2).is_some_and(|val| *val == Opaque(3)));
+}
+
+#[test]
+fn keys_and_values() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+
+    let mut keys: Vec<_> = cache.keys().copied().collect();
+    keys.sort();
+    assert_eq!(keys, vec![1, 2, 3]);
+
+    for val in cache.values_mut() {
+        *val += 1;
+    }
+    let mut values: Vec<_> = cache.values().copied().collect();
+    values.sort();
+    assert_eq!(values, vec![11, 21, 31]);
+    assert_eq!(cache.peek_lru(), Some((&1, &11)));
+}
+
+#[test]
+fn resize_drain_evicts_unconsumed_pairs() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    for i in 0..4 {
+        cache.insert(i, i * 10);
+    }
+
+    let mut evicted = cache.resize_drain(NonZeroUsize::new(1).unwrap());
+    assert_eq!(evicted.len(), 3);
+    assert_eq!(evicted.next(), Some((0, 0)));
+    drop(evicted);
+
+    assert_eq!(cache.max_len(), 1);
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.peek(&3), Some(&30));
+}
+
+#[test]
+fn clone_is_independent() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+    cache.get(&1);
+
+    let mut copy = cache.clone();
+    assert_eq!(copy.max_len(), 3);
+    assert!(copy.iter_lru().eq(cache.iter_lru()));
+
+    copy.insert(4, 40);
+    assert_eq!(copy.peek(&2), None);
+    assert_eq!(cache.peek(&2), Some(&20));
+    assert_eq!(cache.peek(&4), None);
+    assert_eq!(cache.pop_lru(), Some((2, 20)));
+    assert_eq!(copy.pop_lru(), Some((3, 30)));
+}
+
+#[test]
+fn sync_cache_is_shared_between_threads() {
+    let cache = std::sync::Arc::new(SyncLRUCache::with_max_len(NonZeroUsize::new(8).unwrap()));
+    let writers: Vec<_> = (0..4)
+        .map(|i| {
+            let cache = cache.clone();
+            std::thread::spawn(move || cache.insert(i, i.to_string()))
+        })
+        .collect();
+    for writer in writers {
+        assert_eq!(writer.join().unwrap(), None);
+    }
+
+    assert_eq!(cache.len(), 4);
+    assert_eq!(cache.get(&2), Some("2".to_string()));
+    assert_eq!(cache.remove(&2), Some("2".to_string()));
+    assert_eq!(cache.get(&2), None);
+}
+
+#[test]
+fn touch_protects_from_eviction() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+
+    assert!(cache.touch(&1));
+    assert!(!cache.touch(&3));
+    cache.insert(3, 30);
+
+    assert_eq!(cache.peek(&1), Some(&10));
+    assert_eq!(cache.peek(&2), None);
+    assert_eq!(cache.stats(), CacheStats::default());
+}
+
+#[test]
+fn reserve_and_shrink_keep_max_len() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert(1, 10);
+
+    cache.reserve(100);
+    assert!(cache.capacity() >= 101);
+    cache.shrink_to_fit();
+    assert!(cache.capacity() < 101);
+
+    assert_eq!(cache.max_len(), 2);
+    assert_eq!(cache.peek(&1), Some(&10));
+}
+
+fn filled<P: Policy<i32, i32>>(mut cache: LRUCache<i32, i32, RandomState, P>) -> Vec<i32> {
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.get(&1);
+    cache.insert(3, 30);
+    let mut keys: Vec<_> = cache.keys().copied().collect();
+    keys.sort();
+    keys
+}
+
+#[test]
+fn policies_choose_what_to_evict() {
+    let max_len = NonZeroUsize::new(2).unwrap();
+
+    assert_eq!(filled(LRUCache::with_max_len(max_len)), vec![1, 3]);
+    assert_eq!(
+        filled(LRUCache::with_max_len(max_len).with_policy(Fifo)),
+        vec![2, 3]
+    );
+    assert_eq!(
+        filled(LRUCache::with_max_len(max_len).with_policy(Mru)),
+        vec![2, 3]
+    );
+
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap()).with_policy(Mru);
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+    cache.get(&1);
+    assert_eq!(cache.resize(max_len), vec![(1, 10)]);
+}
+
+/// Evicts the entry holding the largest value
+#[derive(Debug, Default)]
+struct LargestValue {
+    resident: BTreeSet<(i32, i32)>,
+}
+
+impl Policy<i32, i32> for LargestValue {
+    fn on_insert(&mut self, node: PolicyNode<'_, i32, i32>) {
+        self.resident.insert((*node.value(), *node.key()));
+    }
+
+    fn on_access(&mut self, _node: PolicyNode<'_, i32, i32>) -> bool {
+        true
+    }
+
+    fn on_remove(&mut self, node: PolicyNode<'_, i32, i32>) {
+        self.resident.remove(&(*node.value(), *node.key()));
+    }
+
+    fn choose_victim<'a>(&'a self, _nodes: Nodes<'a, i32, i32>) -> Victim<'a, i32> {
+        match self.resident.last() {
+            Some((_, key)) => Victim::Key(key),
+            None => Victim::LeastRecent,
+        }
+    }
+}
+
+#[test]
+fn custom_policy_chooses_any_victim() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 30);
+    let mut cache = cache.with_policy(LargestValue::default());
+    cache.insert(2, 10);
+    cache.insert(3, 20);
+
+    cache.insert(4, 5);
+    assert_eq!(cache.peek(&1), None);
+    cache.remove(&3);
+    cache.insert(5, 1);
+    cache.insert(6, 2);
+    cache.get(&4);
+    cache.insert(7, 3);
+
+    let mut keys: Vec<_> = cache.keys().copied().collect();
+    keys.sort();
+    assert_eq!(keys, vec![5, 6, 7]);
+    assert_eq!(
+        filled(
+            LRUCache::with_max_len(NonZeroUsize::new(2).unwrap())
+                .with_policy(LargestValue::default())
+        ),
+        vec![1, 3]
+    );
+}
+
+#[test]
+fn lfu_keeps_frequently_retrieved_keys() {
+    let mut cache =
+        LRUCache::with_max_len(NonZeroUsize::new(3).unwrap()).with_policy(Lfu::default());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+    for _ in 0..3 {
+        cache.get(&1);
+    }
+    cache.get(&3);
+    cache.get(&2);
+
+    // 2 and 3 are equally accessed, the least recently used of them goes first
+    cache.insert(4, 40);
+    assert_eq!(cache.peek(&3), None);
+
+    cache.insert(2, 21);
+    cache.insert(5, 50);
+    assert_eq!(cache.peek(&4), None);
+    assert_eq!(cache.peek(&1), Some(&10));
+    assert_eq!(cache.peek(&2), Some(&21));
+}
+
+#[test]
+fn switching_policy_keeps_retrieval_counts() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+    cache.get(&1);
+    cache.get(&1);
+    cache.get(&2);
+
+    let mut cache = cache.with_policy(Lfu::default());
+    cache.insert(4, 40);
+    assert_eq!(cache.peek(&3), None);
+    cache.insert(5, 50);
+    assert_eq!(cache.peek(&4), None);
+
+    cache.get(&5);
+    cache.get(&5);
+    cache.set_policy(Lfu::default());
+    cache.insert(6, 60);
+    assert_eq!(cache.peek(&2), None);
+    assert_eq!(cache.stats().hits, 5);
+    assert_eq!(
+        cache.iter_lru().map(|(k, _)| *k).collect::<Vec<_>>(),
+        vec![1, 5, 6]
+    );
+}
+
+#[test]
+fn lfu_keeps_merged_hot_keys() {
+    let mut cache =
+        LRUCache::with_max_len(NonZeroUsize::new(2).unwrap()).with_policy(Lfu::default());
+    cache.insert(1, 10);
+    for _ in 0..5 {
+        cache.get(&1);
+    }
+    cache.insert(2, 20);
+    cache.get(&2);
+
+    let mut shard =
+        LRUCache::with_max_len(NonZeroUsize::new(1).unwrap()).with_policy(Lfu::default());
+    shard.insert(1, 1);
+    cache.merge_with(shard, |existing, incoming| *existing += incoming);
+
+    cache.insert(3, 30);
+    assert_eq!(cache.peek(&1), Some(&11));
+    assert_eq!(cache.peek(&2), None);
+    assert_eq!(cache.peek(&3), Some(&30));
+}
+
+#[test]
+fn two_queue_cache_resists_scans() {
+    let two = NonZeroUsize::new(2).unwrap();
+    let mut cache = TwoQueueCache::with_segments(two, two);
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    assert_eq!(cache.get(&1), Some(&10));
+    assert_eq!(cache.get(&2), Some(&20));
+
+    for key in 100..110 {
+        cache.insert(key, key * 10);
+    }
+    assert_eq!(cache.len(), 4);
+    assert_eq!(cache.peek(&1), Some(&10));
+    assert_eq!(cache.peek(&2), Some(&20));
+
+    // the least recently used protected key is demoted, then evicted first
+    assert_eq!(cache.insert(108, 1080), Some((108, 1080)));
+    assert_eq!(
+        cache.resize(NonZeroUsize::new(1).unwrap(), two),
+        vec![(109, 1090)]
+    );
+    assert_eq!(
+        cache.resize(NonZeroUsize::new(1).unwrap(), NonZeroUsize::new(1).unwrap()),
+        vec![(1, 10)]
+    );
+    assert_eq!(cache.remove(&2), Some(20));
+}
+
+#[test]
+fn weighted_cache_evicts_by_total_weight() {
+    let mut cache = WeightedLRUCache::with_max_weight(10, |_: &i32, val: &String| val.len());
+    cache.insert(1, "aaaa".to_string());
+    cache.insert(2, "bbbb".to_string());
+    assert_eq!(cache.current_weight(), 8);
+
+    cache.insert(3, "cccccc".to_string());
+    assert_eq!(cache.peek(&1), None);
+    assert_eq!(cache.current_weight(), 10);
+
+    let oversized = "d".repeat(11);
+    assert_eq!(cache.insert(4, oversized.clone()), Some((4, oversized)));
+    assert_eq!(cache.len(), 2);
+
+    assert_eq!(
+        cache.insert(2, "b".to_string()),
+        Some((2, "bbbb".to_string()))
+    );
+    assert_eq!(cache.current_weight(), 7);
+    assert_eq!(cache.resize(1), vec![(3, "cccccc".to_string())]);
+    assert_eq!(cache.remove(&2), Some("b".to_string()));
+    assert_eq!(cache.current_weight(), 0);
+}
+
+#[test]
+fn index_does_not_promote() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    cache.insert("a".to_string(), 1);
+    cache.insert("b".to_string(), 2);
+
+    assert_eq!(cache["a"], 1);
+    cache.insert("c".to_string(), 3);
+    assert_eq!(cache.peek("a"), None);
+}
+
+#[test]
+#[should_panic]
+fn index_panics_on_missing_key() {
+    let cache: LRUCache<i32, i32> = LRUCache::new();
+    let _ = cache[&1];
+}
+
+#[test]
+fn get_or_insert_evicts_on_miss() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    assert_eq!(*cache.get_or_insert(1, 10), 10);
+    assert_eq!(*cache.get_or_insert(2, 20), 20);
+    assert_eq!(*cache.get_or_insert(1, 11), 10);
+
+    assert_eq!(*cache.get_or_insert(3, 30), 30);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.peek(&2), None);
+}
+
+#[test]
+fn pop_mru_takes_back_last_insertion() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.get(&1);
+
+    assert_eq!(cache.pop_mru(), Some((1, 10)));
+    assert_eq!(cache.pop_mru(), Some((2, 20)));
+    assert_eq!(cache.pop_mru(), None);
+}
+
+#[test]
+fn get_many_mut_requires_distinct_present_keys() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+
+    let [first, second] = cache.get_many_mut([&2, &1]).unwrap();
+    std::mem::swap(first, second);
+    assert_eq!(cache.peek_lru(), Some((&3, &30)));
+    assert_eq!(cache.pop_mru(), Some((1, 20)));
+
+    assert!(cache.get_many_mut([&2, &2]).is_none());
+    assert!(cache.get_many_mut([&3, &4]).is_none());
+    assert_eq!(cache.peek_lru(), Some((&3, &30)));
+}
+
+#[test]
+fn is_empty_follows_len() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(1).unwrap());
+    assert!(cache.is_empty());
+    cache.insert(1, 10);
+    assert!(!cache.is_empty());
+    cache.pop_lru();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn insert_and_get_returns_stored_value() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    assert_eq!(*cache.insert_and_get(1, 10), 10);
+    assert_eq!(*cache.insert_and_get(2, 20), 20);
+    assert_eq!(*cache.insert_and_get(1, 11), 11);
+
+    assert_eq!(*cache.insert_and_get(3, 30), 30);
+    assert_eq!(cache.peek(&2), None);
+    assert_eq!(cache.stats(), CacheStats::default());
+}
+
+#[test]
+fn try_insert_keeps_present_value() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    assert_eq!(cache.try_insert(1, 10), Ok(&10));
+    assert_eq!(cache.try_insert(2, 20), Ok(&20));
+
+    assert_eq!(cache.try_insert(1, 11), Err((1, 11)));
+    assert_eq!(cache.peek_lru(), Some((&2, &20)));
+    assert_eq!(cache.try_insert(3, 30), Ok(&30));
+    assert_eq!(cache.peek(&1), Some(&10));
+}
+
+#[test]
+fn insert_with_outcome_tells_replacement_from_eviction() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(2).unwrap());
+    assert_eq!(cache.insert_with_outcome(1, 10), InsertOutcome::Inserted);
+    assert_eq!(cache.insert_with_outcome(2, 20), InsertOutcome::Inserted);
+    assert_eq!(
+        cache.insert_with_outcome(1, 11),
+        InsertOutcome::Replaced(1, 10)
+    );
+    assert_eq!(
+        cache.insert_with_outcome(3, 30),
+        InsertOutcome::Evicted(2, 20)
+    );
+
+    let evicted = Rc::new(RefCell::new(Vec::new()));
+    let sink = evicted.clone();
+    cache.on_evict(move |key, val, _| sink.borrow_mut().push((key, val)));
+    assert_eq!(cache.insert_with_outcome(4, 40), InsertOutcome::Inserted);
+    assert_eq!(*evicted.borrow(), vec![(1, 11)]);
+
+    let mut disabled = LRUCache::with_max_len_or_disabled(0);
+    assert_eq!(
+        disabled.insert_with_outcome(1, 10),
+        InsertOutcome::Evicted(1, 10)
+    );
+}
+
+#[test]
+fn builder_combines_options() {
+    let evicted = Rc::new(Cell::new(0));
+    let counter = evicted.clone();
+    let mut cache = LRUCache::builder()
+        .max_len(NonZeroUsize::new(2).unwrap())
+        .hasher(BuildHasherDefault::<Fnv>::default())
+        .on_evict(move |_: i32, _: i32, _| counter.set(counter.get() + 1))
+        .eviction_history(4)
+        .build();
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+    cache.insert(4, 40);
+
+    assert_eq!(cache.max_len(), 2);
+    assert_eq!(evicted.get(), 2);
+    assert_eq!(cache.recent_evictions().collect::<Vec<_>>(), vec![&1, &2]);
+    assert_eq!(LRUCache::<i32, i32>::builder().build().max_len(), 1);
+}
+
+#[test]
+fn rehashing_keeps_entries_and_order() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.insert(3, 30);
+    cache.get(&1);
+
+    let mut cache = cache.rehash_with(BuildHasherDefault::<Fnv>::default());
+    assert_eq!(
+        cache.iter_lru().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+        vec![(2, 20), (3, 30), (1, 10)]
+    );
+    assert_eq!(cache.get(&3), Some(&30));
+    assert_eq!(cache.stats().hits, 2);
+
+    cache.insert(4, 40);
+    assert_eq!(cache.peek(&2), None);
+    assert_eq!(cache.len(), 3);
+}
+
+#[test]
+fn tie_break_orders_equally_retrieved_keys() {
+    let evicted_with = |tie_break| {
+        let mut cache = LRUCache::builder()
+            .max_len(NonZeroUsize::new(3).unwrap())
+            .policy(Lfu::default())
+            .tie_break(tie_break)
+            .build();
+        cache.insert(1, 10);
+        cache.insert(2, 20);
+        cache.insert(3, 30);
+        cache.get(&2);
+        cache.get(&1);
+        cache.get(&3);
+        cache.insert(4, 40);
+        (1..=3).find(|key| cache.peek(key).is_none())
+    };
+
+    assert_eq!(evicted_with(TieBreak::Recency), Some(2));
+    assert_eq!(evicted_with(TieBreak::Fifo), Some(1));
+    assert_eq!(evicted_with(TieBreak::Lifo), Some(3));
+}
+
+#[test]
+fn from_hashmap_keeps_every_pair() {
+    let map: std::collections::HashMap<_, _> = (0..5).map(|i| (i, i * 10)).collect();
+    let cache: LRUCache<i32, i32> = LRUCache::from(map);
+    assert_eq!(cache.len(), 5);
+    assert_eq!(cache.max_len(), 5);
+    assert_eq!(cache.peek(&4), Some(&40));
+
+    let empty: LRUCache<i32, i32> = std::collections::HashMap::new().into();
+    assert_eq!(empty.max_len(), 1);
+}
+
+#[test]
+fn into_hashmap_moves_every_pair() {
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(3).unwrap());
+    cache.insert(1, "a".to_string());
+    cache.insert(2, "b".to_string());
+
+    let map = cache.into_hashmap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[&1], "a");
+    assert_eq!(map[&2], "b");
+}
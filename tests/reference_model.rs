@@ -0,0 +1,81 @@
+use std::num::NonZeroUsize;
+
+use lru_cache::{LRUCache, Op, OpResult};
+
+/// Straightforward LRU: entries are kept from the least recently used to the most recently used.
+struct Model {
+    entries: Vec<(u8, u32)>,
+    max_len: usize,
+}
+
+impl Model {
+    fn apply(&mut self, op: Op<u8, u32>) -> OpResult<u8, u32> {
+        match op {
+            Op::Insert(key, val) => {
+                let replaced = self.take(key);
+                if replaced.is_none() && self.entries.len() == self.max_len {
+                    self.entries.remove(0);
+                }
+                self.entries.push((key, val));
+                OpResult::Insert(replaced)
+            }
+            Op::Get(key) => {
+                let found = self.take(key);
+                if let Some(pair) = found {
+                    self.entries.push(pair);
+                }
+                OpResult::Get(found.map(|(_, val)| val))
+            }
+            Op::Remove(key) => OpResult::Remove(self.take(key).map(|(_, val)| val)),
+            Op::Resize(new_max_len) => {
+                let new_max_len = new_max_len.get();
+                let to_remove = self.entries.len().saturating_sub(new_max_len);
+                self.max_len = new_max_len;
+                OpResult::Resize(self.entries.drain(..to_remove).collect())
+            }
+        }
+    }
+
+    fn take(&mut self, key: u8) -> Option<(u8, u32)> {
+        let pos = self.entries.iter().position(|(k, _)| *k == key)?;
+        Some(self.entries.remove(pos))
+    }
+}
+
+/// Linear congruential generator, good enough to produce a reproducible operation stream.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as u32
+    }
+}
+
+#[test]
+fn matches_reference_model() {
+    let mut rng = Lcg(42);
+    let mut cache = LRUCache::with_max_len(NonZeroUsize::new(4).unwrap());
+    let mut model = Model {
+        entries: Vec::new(),
+        max_len: 4,
+    };
+
+    for step in 0..10_000 {
+        let key = (rng.next() % 12) as u8;
+        let op = match rng.next() % 10 {
+            0..=4 => Op::Insert(key, rng.next()),
+            5..=7 => Op::Get(key),
+            8 => Op::Remove(key),
+            _ => Op::Resize(NonZeroUsize::new(rng.next() as usize % 8 + 1).unwrap()),
+        };
+
+        let expected = model.apply(op.clone());
+        let actual = cache.apply(op.clone());
+        assert_eq!(actual, expected, "step {step}: {op:?}");
+        assert_eq!(cache.len(), model.entries.len());
+    }
+}